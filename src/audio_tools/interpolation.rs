@@ -0,0 +1,150 @@
+use core::f32::consts::PI;
+#[cfg(feature = "no_std")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+/// Half the amount of samples the windowed sinc interpolation is reading on each side
+const SINC_HALF_WIDTH: isize = 4;
+
+/// All possible interpolation modes with which grains read between two samples of the audio
+/// source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Reads the sample in front of the position (drop-sample)
+    None,
+    /// Linear interpolation between the two neighbouring samples
+    Linear,
+    /// 4-point cubic Hermite (Catmull-Rom) interpolation
+    Hermite,
+    /// 4-point, 3rd order Lagrange interpolation
+    Lagrange,
+    /// 8-point sinc interpolation with a Lanczos window
+    Sinc,
+}
+
+impl Interpolation {
+    /// Interpolates the value at `fraction` (between 0 and 1) behind the sample at offset 0.
+    /// The neighbouring samples are read through `sample` by their offset to it.
+    pub fn interpolate(self, fraction: f32, sample: impl Fn(isize) -> f32) -> f32 {
+        match self {
+            Interpolation::None => sample(0),
+            Interpolation::Linear => {
+                let (first, next) = (sample(0), sample(1));
+                first + (next - first) * fraction
+            }
+            Interpolation::Hermite => {
+                let (y0, y1, y2, y3) = (sample(-1), sample(0), sample(1), sample(2));
+
+                let c1 = 0.5 * (y2 - y0);
+                let c2 = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+                let c3 = 0.5 * (y3 - y0) + 1.5 * (y1 - y2);
+                ((c3 * fraction + c2) * fraction + c1) * fraction + y1
+            }
+            Interpolation::Lagrange => {
+                let (y0, y1, y2, y3) = (sample(-1), sample(0), sample(1), sample(2));
+                let t = fraction;
+
+                y0 * -t * (t - 1.0) * (t - 2.0) / 6.0 + y1 * (t + 1.0) * (t - 1.0) * (t - 2.0) / 2.0
+                    - y2 * (t + 1.0) * t * (t - 2.0) / 2.0
+                    + y3 * (t + 1.0) * t * (t - 1.0) / 6.0
+            }
+            Interpolation::Sinc => {
+                let mut sum = 0.0;
+                let mut weights = 0.0;
+                for offset in 1 - SINC_HALF_WIDTH..=SINC_HALF_WIDTH {
+                    let weight = lanczos(fraction - offset as f32);
+                    sum += sample(offset) * weight;
+                    weights += weight;
+                }
+
+                // normalized, so constant signals keep their level
+                sum / weights
+            }
+        }
+    }
+}
+
+fn lanczos(x: f32) -> f32 {
+    if x.abs() < f32::EPSILON {
+        1.0
+    } else {
+        let a = SINC_HALF_WIDTH as f32;
+        let x = PI * x;
+        a * x.sin() * (x / a).sin() / (x * x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // one period of a sine is 32 samples long
+    const PERIOD: f32 = 32.0;
+
+    fn sine(position: f32) -> f32 {
+        (2.0 * PI * position / PERIOD).sin()
+    }
+
+    /// Resamples the sine with the given ratio and returns the largest deviation from it.
+    fn resampling_error(interpolation: Interpolation, ratio: f32) -> f32 {
+        let mut error: f32 = 0.0;
+
+        for step in 0..100 {
+            let position = 8.0 + step as f32 * ratio;
+            let index = position.floor();
+            let value =
+                interpolation.interpolate(position - index, |offset| sine(index + offset as f32));
+
+            error = error.max((value - sine(position)).abs());
+        }
+
+        error
+    }
+
+    #[test]
+    fn hit_the_samples_exactly() {
+        for interpolation in [
+            Interpolation::None,
+            Interpolation::Linear,
+            Interpolation::Hermite,
+            Interpolation::Lagrange,
+            Interpolation::Sinc,
+        ] {
+            assert!(resampling_error(interpolation, 1.0) < 0.0001);
+        }
+    }
+
+    #[test]
+    fn resample_a_sine() {
+        for ratio in [0.5, 0.75, 1.5, 2.25] {
+            assert!(resampling_error(Interpolation::Linear, ratio) < 0.005);
+            assert!(resampling_error(Interpolation::Hermite, ratio) < 0.001);
+            assert!(resampling_error(Interpolation::Lagrange, ratio) < 0.0005);
+            assert!(resampling_error(Interpolation::Sinc, ratio) < 0.003);
+        }
+
+        // lags behind by up to a whole sample
+        assert!(resampling_error(Interpolation::None, 0.5) > 0.05);
+    }
+
+    #[test]
+    fn reference_values() {
+        let ramp = |offset: isize| offset as f32;
+        let square = |offset: isize| (offset * offset) as f32;
+
+        assert!(Interpolation::None.interpolate(0.75, ramp) == 0.0);
+        assert!(Interpolation::Linear.interpolate(0.25, ramp) == 0.25);
+
+        // both cubic interpolations reproduce a parabola exactly
+        assert!((Interpolation::Hermite.interpolate(0.5, square) - 0.25).abs() < 0.0001);
+        assert!((Interpolation::Lagrange.interpolate(0.5, square) - 0.25).abs() < 0.0001);
+        assert!((Interpolation::Sinc.interpolate(0.5, |_| 1.0) - 1.0).abs() < 0.0001);
+
+        // the normalized Lanczos kernel, sampled by an impulse
+        let impulse = |offset: isize| (offset == 0) as u8 as f32;
+        assert!((Interpolation::Sinc.interpolate(0.5, impulse) - 0.618_877).abs() < 0.0001);
+        assert!((Interpolation::Sinc.interpolate(0.25, impulse) - 0.893_389).abs() < 0.0001);
+        assert!((Interpolation::Sinc.interpolate(0.5, ramp) - 0.5).abs() < 0.0001);
+        assert!((Interpolation::Sinc.interpolate(0.25, ramp) - 0.264_452).abs() < 0.0001);
+    }
+}
@@ -1,7 +1,33 @@
+pub mod interpolation;
+
+use core::f32::consts::FRAC_PI_2;
+#[cfg(feature = "no_std")]
+#[allow(unused_imports)]
 use micromath::F32Ext;
 
 pub fn soft_clip(sample: f32) -> f32 {
-    sample.atan_norm()
+    #[cfg(feature = "no_std")]
+    return sample.atan_norm();
+
+    #[cfg(not(feature = "no_std"))]
+    return sample.atan() * core::f32::consts::FRAC_2_PI;
+}
+
+/// Returns the left and right gain for a pan position between 0 (left) and 1 (right).
+///
+/// Uses a constant-power (sine/cosine) pan law, so the summed power stays the same across the
+/// whole stereo field.
+pub fn constant_power_pan(pan: f32) -> (f32, f32) {
+    let angle = pan.clamp(0.0, 1.0) * FRAC_PI_2;
+    (angle.cos(), angle.sin())
+}
+
+/// Returns the left and right gain for a balance position between 0 (left) and 1 (right).
+///
+/// Both sides keep their full level in the center, so stereo material stays untouched.
+pub fn balance(pan: f32) -> (f32, f32) {
+    let pan = pan.clamp(0.0, 1.0);
+    ((2.0 - 2.0 * pan).min(1.0), (2.0 * pan).min(1.0))
 }
 
 #[cfg(test)]
@@ -24,4 +50,26 @@ mod tests {
             assert!(processed >= (-1.0 - f32::EPSILON));
         }
     }
+
+    #[test]
+    fn constant_power() {
+        for i in 0..=10 {
+            let (left, right) = constant_power_pan(i as f32 / 10.0);
+            assert!((left * left + right * right - 1.0).abs() < 0.001);
+        }
+
+        let (left, right) = constant_power_pan(0.0);
+        assert!(left > 0.999 && right.abs() < 0.001);
+
+        let (left, right) = constant_power_pan(1.0);
+        assert!(left.abs() < 0.001 && right > 0.999);
+    }
+
+    #[test]
+    fn balance_keeps_center() {
+        assert!(balance(0.5) == (1.0, 1.0));
+        assert!(balance(0.0) == (1.0, 0.0));
+        assert!(balance(1.0) == (0.0, 1.0));
+        assert!(balance(0.75) == (0.5, 1.0));
+    }
 }
@@ -1,41 +1,320 @@
+use super::audio_tools::interpolation::Interpolation;
+use super::audio_tools::{balance, constant_power_pan};
+use super::manager::MAX_CHANNELS;
 use super::pointer_wrapper::BufferSlice;
+use super::sample::Sample;
+use super::window_table::WindowTables;
 
+use core::time::Duration;
 use core::{
     f32::consts::{PI, TAU},
     ops::Neg,
 };
-use num_traits::AsPrimitive;
 
+#[cfg(feature = "no_std")]
 #[allow(unused_imports)]
 use micromath::F32Ext;
 
-/// All possible window functions that can be applied to a given audio source
-#[derive(Debug, Clone, Copy)]
+/// All possible window functions that can be applied to a given audio source. Windows with a
+/// shape parameter read it from `window_param` (between 0 and 1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WindowFunction {
     Sine,
     Hann,
+    /// Raised cosine that keeps 0.08 at both edges
     Hamming,
+    /// The parameter sets the width of the bell
     Gaussian,
+    /// Flat top with cosine tapers, the parameter sets the portion of the grain that is tapered
     Tukey,
+    /// The parameter sets the steepness of the slopes
     Trapezodial,
+    Blackman,
+    BlackmanHarris,
+    Nuttall,
+    /// The parameter sets beta between 1 and 20, scaled to reach 0 at both edges
+    Kaiser,
+    /// Parabola
+    Welch,
+    /// Flat top with smooth tapers, the parameter sets the portion of the grain that is tapered
+    PlanckTaper,
+    /// Short attack followed by an exponential decay, the parameter sets how fast it decays
+    Percussive,
+    /// Flat top with short cosine fades, the parameter sets their length between 1% and 10% of
+    /// the grain
+    Rectangular,
+    /// Envelope set by the user (see `Granulator::set_custom_window`), a sine window until then
+    Custom,
+}
+
+impl WindowFunction {
+    /// Returns the value of the window at the given phase (between 0 and 1).
+    pub fn value_at(self, phase: f32, param: f32) -> f32 {
+        let phase = phase.clamp(0.0, 1.0);
+        let value = match self {
+            // custom windows are only known to the window tables
+            WindowFunction::Sine | WindowFunction::Custom => (PI * phase).sin(),
+            WindowFunction::Hann => 0.5 * (1.0 - (TAU * phase).cos()),
+            WindowFunction::Hamming => 0.54 - 0.46 * (TAU * phase).cos(),
+
+            WindowFunction::Gaussian => {
+                let sigma = 0.5 * (param + 0.01);
+
+                (((phase - 0.5) / (sigma * 0.5)).powf(2.0) * -0.5).exp()
+            }
+
+            WindowFunction::Tukey => {
+                let taper = param.clamp(0.01, 1.0) / 2.0;
+                let edge = phase.min(1.0 - phase);
+
+                if edge < taper {
+                    0.5 * (1.0 - (PI * edge / taper).cos())
+                } else {
+                    1.0
+                }
+            }
+            WindowFunction::Trapezodial => {
+                let slope = param * 5.0 + 1.0;
+                let incrementing = slope * phase;
+                let decrementing = slope.neg() * (phase - (slope - 1.0) / slope) + 1.0;
+                if phase < 0.5 {
+                    if incrementing < 1.0 {
+                        incrementing
+                    } else {
+                        1.0
+                    }
+                } else {
+                    if decrementing < 1.0 {
+                        decrementing
+                    } else {
+                        1.0
+                    }
+                }
+            }
+
+            WindowFunction::Blackman => cosine_sum(phase, &[0.42, 0.5, 0.08]),
+            WindowFunction::BlackmanHarris => {
+                cosine_sum(phase, &[0.35875, 0.48829, 0.14128, 0.01168])
+            }
+            WindowFunction::Nuttall => {
+                cosine_sum(phase, &[0.355_768, 0.487_396, 0.144_232, 0.012_604])
+            }
+
+            WindowFunction::Kaiser => {
+                let beta = 1.0 + 19.0 * param.clamp(0.0, 1.0);
+                let x = 2.0 * phase - 1.0;
+
+                // shifted and scaled so it reaches 0 at both edges
+                (bessel_i0(beta * (1.0 - x * x).max(0.0).sqrt()) - 1.0) / (bessel_i0(beta) - 1.0)
+            }
+            WindowFunction::Welch => {
+                let x = 2.0 * phase - 1.0;
+                1.0 - x * x
+            }
+            WindowFunction::PlanckTaper => {
+                let taper = param.clamp(0.01, 1.0) / 2.0;
+                let edge = phase.min(1.0 - phase);
+
+                if edge <= 0.0 {
+                    0.0
+                } else if edge < taper {
+                    let exponent = (taper / edge - taper / (taper - edge)).clamp(-30.0, 30.0);
+                    1.0 / (1.0 + exponent.exp())
+                } else {
+                    1.0
+                }
+            }
+            WindowFunction::Percussive => {
+                if phase < PERCUSSIVE_ATTACK {
+                    (0.5 * PI * phase / PERCUSSIVE_ATTACK).sin()
+                } else {
+                    let decay = 2.0 + 18.0 * param.clamp(0.0, 1.0);
+                    let t = (phase - PERCUSSIVE_ATTACK) / (1.0 - PERCUSSIVE_ATTACK);
+                    let end = (-decay).exp();
+
+                    // shifted and scaled so it reaches 0 at the end
+                    (((-decay * t).exp() - end) / (1.0 - end)).max(0.0)
+                }
+            }
+            WindowFunction::Rectangular => {
+                let fade = 0.01 + 0.09 * param.clamp(0.0, 1.0);
+                let edge = phase.min(1.0 - phase);
+
+                if edge < fade {
+                    0.5 * (1.0 - (PI * edge / fade).cos())
+                } else {
+                    1.0
+                }
+            }
+        };
+
+        // rounding errors must not push it out of range
+        value.clamp(0.0, 1.0)
+    }
+}
+
+/// Warps the phase of a window (between 0 and 1), so its center moves to somewhere between 5%
+/// (fast attack, long release) and 95% (long attack, fast release) of the grain, depending on the
+/// skew (between 0 and 1). The phase is left untouched at a skew of 0.5.
+fn skew_phase(phase: f32, skew: f32) -> f32 {
+    let center = 0.05 + 0.9 * skew.clamp(0.0, 1.0);
+
+    if phase < center {
+        0.5 * phase / center
+    } else {
+        0.5 + 0.5 * (phase - center) / (1.0 - center)
+    }
+}
+
+/// Portion of the grain the percussive window takes to reach its peak
+const PERCUSSIVE_ATTACK: f32 = 0.02;
+
+/// Generalized cosine window `a0 - a1 cos(2 pi x) + a2 cos(4 pi x) - ...`
+fn cosine_sum(phase: f32, coefficients: &[f32]) -> f32 {
+    coefficients
+        .iter()
+        .enumerate()
+        .map(|(k, a)| {
+            let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+            sign * a * (TAU * k as f32 * phase).cos()
+        })
+        .sum()
+}
+
+/// Zeroth order modified Bessel function of the first kind, as its power series
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+
+    for k in 1..64 {
+        term *= half / k as f32;
+        let squared = term * term;
+        sum += squared;
+        if squared < sum * 1e-8 {
+            break;
+        }
+    }
+
+    sum
+}
+
+/// Decides which channels of a multi-channel audio buffer a grain is reading
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelMode {
+    /// Reads all channels and spreads them across the stereo field, so stereo material keeps its
+    /// stereo image
+    All,
+    /// Reads only the given channel
+    Fixed(usize),
+    /// Reads a randomly chosen channel per grain
+    Random,
+}
+
+/// Explicit parameters of a single grain started with `Granulator::spawn_grain`, instead of the
+/// randomly spread ones
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GrainSpec {
+    /// Start of the grain in the audio buffer in samples (frames of multi-channel buffers)
+    pub offset: usize,
+    /// Length of the grain in samples, kept within the audio buffer
+    pub length: usize,
+    /// Playback speed, 2 is an octave up
+    pub pitch: f32,
+    /// Volume between 0 and 1
+    pub velocity: f32,
+    pub window: WindowFunction,
+    pub window_param: f32,
+    /// Position in the stereo field between 0 (left) and 1 (right)
+    pub pan: f32,
+    /// Time until the grain starts
+    pub delay: Duration,
+    /// Interpolation of this grain, the one of the granulator if `None`
+    pub interpolation: Option<Interpolation>,
+}
+
+impl Default for GrainSpec {
+    fn default() -> Self {
+        GrainSpec {
+            offset: 0,
+            length: 480,
+            pitch: 1.0,
+            velocity: 1.0,
+            window: WindowFunction::Sine,
+            window_param: 0.0,
+            pan: 0.5,
+            delay: Duration::ZERO,
+            interpolation: None,
+        }
+    }
+}
+
+/// Parameters with which a grain is being started
+#[derive(Debug, Clone, Copy)]
+pub struct GrainParameters {
+    pub window: WindowFunction,
+    pub window_param: f32,
+    pub window_skew: f32, // moves the peak of the envelope, symmetric at 0.5
+    pub morph: Option<(WindowFunction, f32)>, // window blended in by the given amount (0..1)
+    pub pitch: f32,
+    pub velocity: f32,
+    pub pan: f32,
+    pub channel: Option<usize>, // all channels if `None`
+    pub interpolation: Interpolation,
+    pub length: Option<usize>, // length of the source slice if `None`
+    pub reverse: bool,
+    pub ping_pong: bool, // bounces between both ends of the source slice instead of wrapping
+}
+
+impl Default for GrainParameters {
+    fn default() -> Self {
+        GrainParameters {
+            window: WindowFunction::Sine,
+            window_param: 0.0,
+            window_skew: 0.5,
+            morph: None,
+            pitch: 1.0,
+            velocity: 1.0,
+            pan: 0.5,
+            channel: None,
+            interpolation: Interpolation::Linear,
+            length: None,
+            reverse: false,
+            ping_pong: false,
+        }
+    }
 }
 
 #[derive(Debug)]
-pub struct Grain<T: AsPrimitive<f32>> {
+pub struct Grain<T: Sample> {
     // envelope variables
     window: WindowFunction,
     window_param: f32,
+    window_skew: f32,
+    morph: Option<(WindowFunction, f32)>,
     envelope_position: f32, // between 0..grain_length (in samples)
     envelope_value: f32,    // between 0..1
+    envelope_length: usize, // differs from the source length when reading a mip map level
+
+    // fade out, only active if its length is not 0
+    fade_out_position: usize,
+    fade_out_length: usize,
 
     // source variables
     source_sub_slice: BufferSlice<T>, // slice as pointer of any numeric type
     source_position: f32,             // between 0..grain_length (in samples)
-    source_value: f32,                // between 0..1
+    direction: f32,                   // 1 for forward and -1 for backward playback
+    ping_pong: bool,
 
     // parameters
     pitch: f32,
     velocity: f32,
+    channel: Option<usize>,
+    interpolation: Interpolation,
+
+    // (left, right) gain of every channel that is being read, only the first one is being used
+    // when a single channel is read
+    channel_gains: [(f32, f32); MAX_CHANNELS],
 
     // grain variables
     pub finished: bool,
@@ -44,27 +323,39 @@ pub struct Grain<T: AsPrimitive<f32>> {
     pub id: usize,
 }
 
-impl<T: AsPrimitive<f32>> Grain<T> {
-    pub fn new(
-        id: usize,
-        source_sub_slice: BufferSlice<T>,
-        window: WindowFunction,
-        window_param: f32,
-        pitch: f32,
-        velocity: f32,
-    ) -> Self {
+impl<T: Sample> Grain<T> {
+    pub fn new(id: usize, source_sub_slice: BufferSlice<T>, parameters: GrainParameters) -> Self {
+        // mono buffers only have a single channel to read
+        let channel = match source_sub_slice.channels {
+            1 => Some(0),
+            channels => parameters.channel.map(|channel| channel.min(channels - 1)),
+        };
+
         Grain {
-            window,
-            window_param,
+            window: parameters.window,
+            window_param: parameters.window_param,
+            window_skew: parameters.window_skew,
+            morph: parameters.morph,
             envelope_position: 0.0,
             envelope_value: 0.0,
+            envelope_length: parameters.length.unwrap_or(source_sub_slice.length),
+
+            fade_out_position: 0,
+            fade_out_length: 0,
 
+            channel_gains: get_channel_gains(source_sub_slice.channels, channel, parameters.pan),
+            source_position: match parameters.reverse {
+                true => source_sub_slice.length.saturating_sub(1) as f32,
+                false => 0.0,
+            },
+            direction: if parameters.reverse { -1.0 } else { 1.0 },
+            ping_pong: parameters.ping_pong,
             source_sub_slice,
-            source_position: 0.0,
-            source_value: 0.0,
 
-            pitch,
-            velocity,
+            pitch: parameters.pitch,
+            velocity: parameters.velocity,
+            channel,
+            interpolation: parameters.interpolation,
 
             finished: false,
 
@@ -72,49 +363,19 @@ impl<T: AsPrimitive<f32>> Grain<T> {
         }
     }
 
-    fn get_envelope_value(&self) -> f32 {
-        let size = self.source_sub_slice.length as f32;
-        match self.window {
-            WindowFunction::Sine => ((PI * self.envelope_position) / size).sin(),
-            WindowFunction::Hann => 0.5 * (1.0 - (TAU * self.envelope_position / size).cos()),
-            WindowFunction::Hamming => 0.54 * (0.46 - (TAU * self.envelope_position / size).cos()),
-
-            WindowFunction::Gaussian => {
-                // window parameter
-                let sigma = 0.5 * (self.window_param + 0.01);
+    fn get_envelope_value(&self, window_tables: &WindowTables) -> f32 {
+        let phase = skew_phase(
+            self.envelope_position / self.envelope_length as f32,
+            self.window_skew,
+        );
+        let value = window_tables.value_at(self.window, self.window_param, phase);
 
-                (((self.envelope_position - size / 2.0) / (sigma * size / 2.0)).powf(2.0) * -0.5)
-                    .exp()
-            }
-
-            WindowFunction::Tukey => {
-                // window parameter
-                let truncation = 2.5 * (self.window_param + 0.01);
-
-                let value =
-                    1.0 / (2.0 * truncation) * (1.0 - (TAU * self.envelope_position / size).cos());
-                value.clamp(0.0, 1.0)
-            }
-            WindowFunction::Trapezodial => {
-                // window parameter
-                let slope = self.window_param * 5.0 + 1.0;
-                let step = self.envelope_position / size;
-                let incrementing = slope * step;
-                let decrementing = slope.neg() * (step - (slope - 1.0) / slope) + 1.0;
-                if step < 0.5 {
-                    if incrementing < 1.0 {
-                        incrementing
-                    } else {
-                        1.0
-                    }
-                } else {
-                    if decrementing < 1.0 {
-                        decrementing
-                    } else {
-                        1.0
-                    }
-                }
+        match self.morph {
+            Some((target, amount)) => {
+                let target_value = window_tables.value_at(target, self.window_param, phase);
+                value + (target_value - value) * amount
             }
+            None => value,
         }
     }
 
@@ -122,19 +383,33 @@ impl<T: AsPrimitive<f32>> Grain<T> {
         &self,
         source_stream: &BufferSlice<T>,
         position: &f32,
+        channel: usize,
     ) -> f32 {
-        let first = source_stream.get_f32_value_at(&mut (*position as usize));
-        let next = source_stream.get_f32_value_at(&mut (*position as usize + 1));
-        (first + next) * 0.5
+        let index = position.floor();
+        let fraction = position - index;
+
+        self.interpolation.interpolate(fraction, |offset| {
+            // samples in front of the slice are truncated to its first one
+            let mut position = (index as isize + offset).max(0) as usize;
+            source_stream.get_f32_value_at(&mut position, channel)
+        })
     }
 
-    fn update_envelope(&mut self) -> f32 {
+    fn update_envelope(&mut self, window_tables: &WindowTables) -> f32 {
         if !self.finished {
             // calcualte new value
-            self.envelope_value = self.get_envelope_value();
+            self.envelope_value = self.get_envelope_value(window_tables);
+
+            if self.fade_out_length > 0 {
+                self.fade_out_position += 1;
+                self.envelope_value *=
+                    1.0 - self.fade_out_position as f32 / self.fade_out_length as f32;
+            }
 
             // finish grain if it reaches end
-            if self.envelope_position < self.source_sub_slice.length as f32 {
+            if self.envelope_position < self.envelope_length as f32
+                && self.fade_out_position < self.fade_out_length.max(1)
+            {
                 self.envelope_position += 1.0;
             } else {
                 self.finished = true;
@@ -145,25 +420,426 @@ impl<T: AsPrimitive<f32>> Grain<T> {
         self.envelope_value
     }
 
-    fn update_source_sample(&mut self) -> f32 {
+    fn update_source_position(&mut self) {
         if !self.finished {
             // move playhead
-            self.source_position += self.pitch;
+            self.source_position += self.pitch * self.direction;
 
-            // wrap around
-            if self.source_position >= self.source_sub_slice.length as f32 - 1.0 {
-                self.source_position -= self.source_sub_slice.length as f32;
+            let length = self.source_sub_slice.length as f32;
+            let last = (length - 1.0).max(0.0);
+
+            if self.ping_pong {
+                // bounce off both ends
+                if self.source_position > last {
+                    self.source_position = 2.0 * last - self.source_position;
+                    self.direction = -1.0;
+                } else if self.source_position < 0.0 {
+                    self.source_position = -self.source_position;
+                    self.direction = 1.0;
+                }
+                self.source_position = self.source_position.clamp(0.0, last);
+            } else if self.source_position >= last && self.direction > 0.0 {
+                // wrap around
+                self.source_position -= length;
+            } else if self.source_position < 0.0 && self.direction < 0.0 {
+                self.source_position += length;
+            }
+        }
+    }
+
+    fn get_source_value(&self, channel: usize) -> f32 {
+        self.get_source_sample_interpolated(&self.source_sub_slice, &self.source_position, channel)
+    }
+
+    /// Returns the next sample of the grain. All channels are being mixed down to mono if the grain
+    /// reads all of them. The envelope is read from the window tables if they hold the window of the
+    /// grain and computed otherwise.
+    pub fn get_next_sample(&mut self, window_tables: &WindowTables) -> f32 {
+        let envelope = self.update_envelope(window_tables);
+        self.update_source_position();
+
+        if self.finished {
+            return 0.0;
+        }
+
+        let source_value = match self.channel {
+            Some(channel) => self.get_source_value(channel),
+            None => {
+                let channels = self.source_sub_slice.channels;
+                let sum: f32 = (0..channels).map(|c| self.get_source_value(c)).sum();
+                sum / channels as f32
+            }
+        };
+
+        envelope * source_value * self.velocity
+    }
+
+    /// Returns how many samples the grain still outputs, including the last silent sample during
+    /// which it finishes.
+    pub fn remaining_samples(&self) -> usize {
+        if self.finished {
+            0
+        } else if self.fade_out_length > 0 {
+            let remaining = self.envelope_length + 1 - self.envelope_position as usize;
+            remaining.min(self.fade_out_length - self.fade_out_position)
+        } else {
+            self.envelope_length + 1 - self.envelope_position as usize
+        }
+    }
+
+    /// Fades the grain out linearly over the given amount of samples and finishes it afterwards.
+    /// A length of 0 finishes the grain immediately. Does nothing if the grain is already fading.
+    pub fn fade_out(&mut self, length: usize) {
+        if length == 0 {
+            self.finished = true;
+        } else if self.fade_out_length == 0 {
+            self.fade_out_length = length;
+        }
+    }
+
+    /// Returns whether the grain is reading from the same audio buffer as the given slice.
+    pub fn reads_from(&self, buffer: &BufferSlice<T>) -> bool {
+        self.source_sub_slice.shares_buffer_with(buffer)
+    }
+
+    /// Adds the next `buffer.len()` samples of the grain onto the given buffer.
+    pub fn add_to_block(&mut self, buffer: &mut [f32], window_tables: &WindowTables) {
+        for sample in buffer.iter_mut() {
+            *sample += self.get_next_sample(window_tables);
+        }
+    }
+
+    /// Adds the next `left.len()` stereo frames of the grain onto the given channel buffers.
+    pub fn add_to_stereo_block(
+        &mut self,
+        left: &mut [f32],
+        right: &mut [f32],
+        window_tables: &WindowTables,
+    ) {
+        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+            let (grain_left, grain_right) = self.get_next_frame(window_tables);
+            *left += grain_left;
+            *right += grain_right;
+        }
+    }
+
+    /// Returns the next frame of the grain panned into the stereo field as (left, right).
+    pub fn get_next_frame(&mut self, window_tables: &WindowTables) -> (f32, f32) {
+        let envelope = self.update_envelope(window_tables);
+        self.update_source_position();
+
+        if self.finished {
+            return (0.0, 0.0);
+        }
+
+        let (left, right) = match self.channel {
+            Some(channel) => {
+                let value = self.get_source_value(channel);
+                (
+                    value * self.channel_gains[0].0,
+                    value * self.channel_gains[0].1,
+                )
+            }
+            None => {
+                let mut frame = (0.0, 0.0);
+                for (channel, gains) in self.channel_gains[..self.source_sub_slice.channels]
+                    .iter()
+                    .enumerate()
+                {
+                    let value = self.get_source_value(channel);
+                    frame.0 += value * gains.0;
+                    frame.1 += value * gains.1;
+                }
+                frame
+            }
+        };
+
+        let amplitude = envelope * self.velocity;
+        (left * amplitude, right * amplitude)
+    }
+}
+
+/// Returns the stereo gains of all channels a grain is reading. A single channel is being panned
+/// with a constant-power pan law. Multiple channels are being spread evenly across the stereo field
+/// from left to right, while the pan position balances between both sides.
+fn get_channel_gains(
+    channels: usize,
+    channel: Option<usize>,
+    pan: f32,
+) -> [(f32, f32); MAX_CHANNELS] {
+    let mut gains = [(0.0, 0.0); MAX_CHANNELS];
+
+    match channel {
+        Some(_) => gains[0] = constant_power_pan(pan),
+        None => {
+            let (left_balance, right_balance) = balance(pan);
+            for (channel, gain) in gains.iter_mut().take(channels).enumerate() {
+                let (left, right) = constant_power_pan(channel as f32 / (channels - 1) as f32);
+                *gain = (left * left_balance, right * right_balance);
+            }
+        }
+    }
+
+    gains
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples the window at 1001 points and returns (first, last, lowest, highest) value.
+    fn window_shape(window: WindowFunction, param: f32) -> (f32, f32, f32, f32) {
+        let values: [f32; 1001] =
+            core::array::from_fn(|i| window.value_at(i as f32 / 1000.0, param));
+
+        (
+            values[0],
+            values[1000],
+            values.iter().copied().fold(f32::MAX, f32::min),
+            values.iter().copied().fold(f32::MIN, f32::max),
+        )
+    }
+
+    #[test]
+    fn windows_start_and_end_at_zero() {
+        for window in [
+            WindowFunction::Sine,
+            WindowFunction::Hann,
+            WindowFunction::Tukey,
+            WindowFunction::Blackman,
+            WindowFunction::BlackmanHarris,
+            WindowFunction::Nuttall,
+            WindowFunction::Kaiser,
+            WindowFunction::Welch,
+            WindowFunction::PlanckTaper,
+            WindowFunction::Percussive,
+            WindowFunction::Rectangular,
+        ] {
+            for param in [0.0, 0.5, 1.0] {
+                let (first, last, lowest, highest) = window_shape(window, param);
+
+                assert!(
+                    first.abs() < 0.001 && last.abs() < 0.001,
+                    "{window:?} {param}"
+                );
+                assert!((highest - 1.0).abs() < 0.001, "{window:?} {param}");
+                assert!(lowest >= 0.0 && highest <= 1.0, "{window:?} {param}");
             }
+        }
+    }
+
+    #[test]
+    fn raise_hamming_and_taper_tukey() {
+        let (first, last, lowest, highest) = window_shape(WindowFunction::Hamming, 0.0);
+        assert!((first - 0.08).abs() < 0.001 && (last - 0.08).abs() < 0.001);
+        assert!((lowest - 0.08).abs() < 0.001 && (highest - 1.0).abs() < 0.001);
+
+        // fully tapered it turns into a Hann window
+        for phase in [0.1, 0.25, 0.6] {
+            let tukey = WindowFunction::Tukey.value_at(phase, 1.0);
+            assert!((tukey - WindowFunction::Hann.value_at(phase, 0.0)).abs() < 0.0001);
+        }
+
+        // half of it is tapered, a quarter on each side
+        assert!(WindowFunction::Tukey.value_at(0.2, 0.5) < 1.0);
+        assert!(WindowFunction::Tukey.value_at(0.25, 0.5) == 1.0);
+        assert!(WindowFunction::Tukey.value_at(0.5, 0.5) == 1.0);
+    }
+
+    #[test]
+    fn keep_the_stereo_image() {
+        // left channel is silent, right channel is fully on
+        let buffer = [0_f32, 1.0].repeat(100);
+        let slice = BufferSlice::from_interleaved_slice(&buffer, 2);
+
+        let mut g = Grain::new(0, slice, GrainParameters::default());
+
+        for _ in 0..100 {
+            let (left, right) = g.get_next_frame(&WindowTables::default());
+            assert!(left.abs() < 0.001);
+            assert!(right >= 0.0);
+        }
+    }
+
+    #[test]
+    fn read_a_single_channel() {
+        let buffer = [0_f32, 1.0].repeat(100);
+        let slice = BufferSlice::from_interleaved_slice(&buffer, 2);
+        let parameters = GrainParameters {
+            channel: Some(1),
+            pan: 0.0,
+            ..Default::default()
+        };
 
-            // interpolate source value
-            self.source_value =
-                self.get_source_sample_interpolated(&self.source_sub_slice, &self.source_position);
+        let mut g = Grain::new(0, slice, parameters);
+
+        let mut left_sum = 0.0;
+        for _ in 0..100 {
+            let (left, right) = g.get_next_frame(&WindowTables::default());
+            assert!(right.abs() < 0.001);
+            left_sum += left;
         }
+        assert!(left_sum > 0.0);
+    }
+
+    #[test]
+    fn interpolate_per_grain() {
+        let buffer: [f32; 1000] = core::array::from_fn(|i| (i * i % 7) as f32);
+        let pitch = 0.75;
+
+        for interpolation in [
+            Interpolation::None,
+            Interpolation::Linear,
+            Interpolation::Hermite,
+            Interpolation::Lagrange,
+            Interpolation::Sinc,
+        ] {
+            let parameters = GrainParameters {
+                window: WindowFunction::Trapezodial,
+                window_param: 1.0,
+                pitch,
+                interpolation,
+                ..Default::default()
+            };
+            let mut g = Grain::new(0, BufferSlice::from_slice(&buffer), parameters);
 
-        self.source_value
+            // the envelope is flat in the middle of the grain
+            for i in 1..=500 {
+                let value = g.get_next_sample(&WindowTables::default());
+                if i > 200 {
+                    let position = i as f32 * pitch;
+                    let index = position.floor();
+                    let expected = interpolation.interpolate(position - index, |offset| {
+                        buffer[(index as isize + offset) as usize]
+                    });
+                    assert!((value - expected).abs() < 0.001);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn skew_the_envelope() {
+        let buffer = [0_f32; 1000];
+        let tables = WindowTables::default();
+
+        for (skew, peak) in [(0.0, 50), (0.5, 500), (1.0, 950)] {
+            let parameters = GrainParameters {
+                window: WindowFunction::Hann,
+                window_skew: skew,
+                ..Default::default()
+            };
+            let mut g = Grain::new(0, BufferSlice::from_slice(&buffer), parameters);
+
+            let envelope: [f32; 1001] = core::array::from_fn(|_| g.update_envelope(&tables));
+            let highest = (0..envelope.len())
+                .max_by(|a, b| envelope[*a].total_cmp(&envelope[*b]))
+                .unwrap();
+
+            assert!(highest == peak);
+            assert!(envelope[0] == 0.0 && envelope[1000].abs() < 0.0001);
+        }
     }
 
-    pub fn get_next_sample(&mut self) -> f32 {
-        self.update_envelope() * self.update_source_sample() * self.velocity
+    #[test]
+    fn morph_between_two_windows() {
+        let buffer = [0_f32; 1000];
+        let tables = WindowTables::default();
+
+        for amount in [0.0, 0.3, 1.0] {
+            let parameters = GrainParameters {
+                window: WindowFunction::Hann,
+                morph: Some((WindowFunction::Rectangular, amount)),
+                ..Default::default()
+            };
+            let mut g = Grain::new(0, BufferSlice::from_slice(&buffer), parameters);
+
+            for i in 0..=100 {
+                let phase = i as f32 / 1000.0;
+                let hann = WindowFunction::Hann.value_at(phase, 0.0);
+                let rectangular = WindowFunction::Rectangular.value_at(phase, 0.0);
+
+                let expected = hann + (rectangular - hann) * amount;
+                assert!((g.update_envelope(&tables) - expected).abs() < 0.0001);
+            }
+        }
+    }
+
+    #[test]
+    fn play_backwards() {
+        let buffer = [0_f32, 1.0, 2.0, 3.0, 4.0];
+        let parameters = GrainParameters {
+            reverse: true,
+            ..Default::default()
+        };
+
+        let mut g = Grain::new(0, BufferSlice::from_slice(&buffer), parameters);
+
+        let positions = [0; 6].map(|_| {
+            g.update_source_position();
+            g.source_position
+        });
+        assert!(positions == [3.0, 2.0, 1.0, 0.0, 4.0, 3.0]);
+    }
+
+    #[test]
+    fn bounce_between_both_ends() {
+        let buffer = [0_f32, 1.0, 2.0, 3.0, 4.0];
+        let parameters = GrainParameters {
+            pitch: 1.5,
+            ping_pong: true,
+            length: Some(100),
+            ..Default::default()
+        };
+
+        let mut g = Grain::new(0, BufferSlice::from_slice(&buffer), parameters);
+
+        let positions = [0; 7].map(|_| {
+            g.update_source_position();
+            g.source_position
+        });
+        assert!(positions == [1.5, 3.0, 3.5, 2.0, 0.5, 1.0, 2.5]);
+    }
+
+    #[test]
+    fn fade_out_and_finish() {
+        let buffer = [1_f32; 1000];
+        let parameters = GrainParameters {
+            window: WindowFunction::Trapezodial,
+            window_param: 1.0,
+            ..Default::default()
+        };
+
+        let mut g = Grain::new(0, BufferSlice::from_slice(&buffer), parameters);
+
+        for _ in 0..500 {
+            g.get_next_sample(&WindowTables::default());
+        }
+
+        g.fade_out(4);
+        assert!(g.remaining_samples() == 4);
+
+        let values = [0; 4].map(|_| g.get_next_sample(&WindowTables::default()));
+        assert!(values == [0.75, 0.5, 0.25, 0.0]);
+        assert!(g.finished && g.remaining_samples() == 0);
+    }
+
+    #[test]
+    fn mix_down_all_channels() {
+        let buffer = [0_f32, 1.0].repeat(100);
+        let slice = BufferSlice::from_interleaved_slice(&buffer, 2);
+        let parameters = GrainParameters {
+            window: WindowFunction::Trapezodial,
+            window_param: 1.0,
+            ..Default::default()
+        };
+
+        let mut g = Grain::new(0, slice, parameters);
+
+        // skip the attack of the envelope
+        for _ in 0..50 {
+            g.get_next_sample(&WindowTables::default());
+        }
+        assert!((g.get_next_sample(&WindowTables::default()) - 0.5).abs() < 0.001);
     }
 }
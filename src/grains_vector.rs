@@ -1,42 +1,38 @@
 #![allow(dead_code)]
 
 use super::grain::Grain;
+use super::grain::GrainParameters;
 use super::grain::WindowFunction;
 use super::manager::MAX_GRAINS;
 use super::pointer_wrapper::BufferSlice;
+use super::window_table::{WindowTable, WindowTables};
 
+use crate::sample::Sample;
 use heapless::Vec;
-use num_traits::AsPrimitive;
 
 #[derive(Debug)]
-pub struct GrainsVector<T: AsPrimitive<f32>> {
+pub struct GrainsVector<T: Sample> {
     grains: Vec<Grain<T>, MAX_GRAINS>,
+    window_tables: WindowTables,
 }
 
-impl<T: AsPrimitive<f32>> GrainsVector<T> {
+impl<T: Sample> GrainsVector<T> {
     pub fn new() -> Self {
-        GrainsVector { grains: Vec::new() }
+        GrainsVector {
+            grains: Vec::new(),
+            window_tables: WindowTables::default(),
+        }
     }
 
     pub fn push_grain(
         &mut self,
         id: usize,
         sub_slice: BufferSlice<T>,
-        window: WindowFunction,
-        window_param: f32,
-        pitch: f32,
-        velocity: f32,
+        parameters: GrainParameters,
     ) -> Result<(), usize> {
         if self
             .grains
-            .push(Grain::new(
-                id,
-                sub_slice,
-                window,
-                window_param,
-                pitch,
-                velocity,
-            ))
+            .push(Grain::new(id, sub_slice, parameters))
             .is_err()
         {
             Err(id)
@@ -64,11 +60,70 @@ impl<T: AsPrimitive<f32>> GrainsVector<T> {
     pub fn get_next_sample(&mut self) -> f32 {
         let mut sample = 0.0;
         for grain in &mut self.grains {
-            sample += grain.get_next_sample();
+            sample += grain.get_next_sample(&self.window_tables);
         }
         sample
     }
 
+    // stereo counterpart of `get_next_sample`
+    pub fn get_next_frame(&mut self) -> (f32, f32) {
+        let mut frame = (0.0, 0.0);
+        for grain in &mut self.grains {
+            let (left, right) = grain.get_next_frame(&self.window_tables);
+            frame.0 += left;
+            frame.1 += right;
+        }
+        frame
+    }
+
+    // renders all grains into the buffer, grain after grain, which yields the exact same sum
+    // as calling `get_next_sample` for every sample
+    pub fn process_block(&mut self, buffer: &mut [f32]) {
+        buffer.fill(0.0);
+        for grain in &mut self.grains {
+            grain.add_to_block(buffer, &self.window_tables);
+        }
+    }
+
+    // stereo counterpart of `process_block`
+    pub fn process_stereo_block(&mut self, left: &mut [f32], right: &mut [f32]) {
+        left.fill(0.0);
+        right.fill(0.0);
+        for grain in &mut self.grains {
+            grain.add_to_stereo_block(left, right, &self.window_tables);
+        }
+    }
+
+    // amount of samples until the first of all grains finishes
+    pub fn samples_until_next_finish(&self) -> Option<usize> {
+        self.grains
+            .iter()
+            .filter(|grain| !grain.finished)
+            .map(|grain| grain.remaining_samples())
+            .min()
+    }
+
+    // renders the selected window and the one it morphs into into their tables, unless they
+    // already hold them. Tables of windows that are `None` are dropped, so their envelopes are
+    // computed exactly.
+    pub fn update_window_tables(
+        &mut self,
+        selected: Option<WindowFunction>,
+        morph_target: Option<WindowFunction>,
+        param: f32,
+    ) {
+        update_window_table(&mut self.window_tables.selected, selected, param);
+        update_window_table(&mut self.window_tables.morph_target, morph_target, param);
+    }
+
+    pub fn get_window_table(&self) -> Option<&WindowTable> {
+        self.window_tables.selected.as_ref()
+    }
+
+    pub fn set_custom_window_table(&mut self, table: WindowTable) {
+        self.window_tables.custom = Some(table);
+    }
+
     pub fn get_grains(&self) -> &Vec<Grain<T>, MAX_GRAINS> {
         &self.grains
     }
@@ -78,6 +133,19 @@ impl<T: AsPrimitive<f32>> GrainsVector<T> {
     }
 }
 
+fn update_window_table(
+    table: &mut Option<WindowTable>,
+    window: Option<WindowFunction>,
+    param: f32,
+) {
+    match (table.as_mut(), window) {
+        (Some(table), Some(window)) if table.matches(window, param) => {}
+        (Some(table), Some(window)) => table.render(window, param),
+        (None, Some(window)) => *table = Some(WindowTable::new(window, param)),
+        (_, None) => *table = None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -91,9 +159,7 @@ mod tests {
         g.push_grain(
             0,
             BufferSlice::from_slice(&SLICE),
-            WindowFunction::Sine,
-            1.0,
-            1.0,
+            GrainParameters::default(),
         )
         .unwrap();
 
@@ -101,7 +167,7 @@ mod tests {
 
         g.remove_grain(0).unwrap();
 
-        assert!(g.grains.len() == 0);
+        assert!(g.grains.is_empty());
     }
 
     #[test]
@@ -111,9 +177,7 @@ mod tests {
         g.push_grain(
             0,
             BufferSlice::from_slice(&SLICE),
-            WindowFunction::Sine,
-            1.0,
-            1.0,
+            GrainParameters::default(),
         )
         .unwrap();
 
@@ -129,15 +193,13 @@ mod tests {
             g.push_grain(
                 i,
                 BufferSlice::from_slice(&SLICE),
-                WindowFunction::Sine,
-                1.0,
-                1.0,
+                GrainParameters::default(),
             )
             .unwrap();
         }
 
         g.flush();
 
-        assert!(g.grains.len() == 0);
+        assert!(g.grains.is_empty());
     }
 }
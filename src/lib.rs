@@ -5,8 +5,14 @@ This library implements a granular algorithm which can be easily placed on two
 threads (i.e. audio callback and update).
 
 # Limitations
-Currently, only **mono** WAV files in f32le (32 bit floating point little endian) format
-are being supported. It is planned to support a lot more PCM based formats and bit depths!
+Audio buffers can be mono or interleaved with up to `MAX_CHANNELS` channels. The samples can
+be stored as `f32`, `f64`, `i16`, packed 24 bit integers (`I24`), `i32` or `u8`, which are
+being scaled to floating point values while playing, so they never have to be copied into an
+`f32` buffer.
+
+With the `std` feature enabled, WAV files (PCM with 8, 16, 24 or 32 bit and IEEE float with 32
+or 64 bit) can be loaded with `load_wav`. WAV files which are already in memory, e.g. in flash,
+can be played in place without copying them with `set_wav_audio_buffer`.
 
 # Platform
 Depending on which platform you are targeting, there are different implementation
@@ -16,6 +22,9 @@ styles.
 When used with `std`, one can simply wrap the `Granulator` in an `Arc<Mutex<_>>`,
 copy its reference counter, start two threads and lock it respectively.
 
+Since the threads may outlive borrowed audio buffers, the buffers have to be either
+`static` or shared with `set_shared_audio_buffer` (requires the `std` feature).
+
 This code snippet acts only as rough demonstration.
 
 ```no_run
@@ -23,30 +32,31 @@ use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 // only outputs sound if an audio buffer is provided
-// which can be swapped out during playback as well
-let granulator = granulator::Granulator::new(48_000); // provide a sample frequency
+// which can be swapped out during playback as well (see `set_buffer_swap`)
+let granulator = granulator::StaticGranulator::new(48_000); // provide a sample frequency
 
 // Wrap it in a reference counter and a Mutex, then clone it
 let audio_ref = Arc::new(Mutex::new(granulator));
 let update_ref = audio_ref.clone();
 
 // Audio callback (mono)
-let buffer = | buffer: Vec<f32> | {
+let callback = | buffer: &mut [f32] | {
     // lock the granulator
     let mut gran = audio_ref.lock().unwrap();
 
-    // calculate samples for the next buffer
-    for mut sample in buffer {
-        sample = gran.get_next_sample();
-    }
+    // calculate all samples for the next buffer at once
+    // (use `process_stereo_block` for stereo output)
+    gran.process_block(buffer);
 };
 
 // Update the granulator itself and its parameters
-// Interval duration should be less than 20ms
+// Grains are started and removed sample-accurately by the audio callback,
+// so the interval can be chosen freely
 let update = | interval_since_last: Duration | {
     // lock the granulator
     let mut gran = update_ref.lock().unwrap();
 
+    // optional, the audio callback already starts and removes all grains
     gran.update_scheduler(interval_since_last);
 
     // set all other parameters of the algorithm
@@ -70,10 +80,10 @@ This code snippet acts only as rough demonstration.
     peripherals = true,
 )]
 mod app {
-    use granulator::Granulator;
+    use granulator::StaticGranulator;
 
     #[shared]
-    struct Shared { granulator: Granulator }
+    struct Shared { granulator: StaticGranulator }
 
     #[local]
     struct Local { buffer: &mut [f32; 64] }
@@ -82,7 +92,7 @@ mod app {
     fn init(ctx: init::Context) -> (Shared, Local, init::Monotonics) {
         // init system and buffer
         (
-            Shared { granulator: Granulator::new(48_000) },
+            Shared { granulator: StaticGranulator::new(48_000) },
             Local { buffer },
             init::Monotonics(),
         )
@@ -101,14 +111,11 @@ mod app {
         let mut buffer = *ctx.local.buffer;
         let mut granulator = *ctx.shared.granulator;
 
-        for sample in buffer {
-            let mut sample = 0.0;
-            granulator.lock(|granulator| {
-                sample = granulator.get_next_sample();
-            });
+        granulator.lock(|granulator| {
+            granulator.process_block(buffer);
+        });
 
-            // push audio into stream
-        }
+        // push audio into stream
     }
 
     #[task(binds = TIM2, shared = [granulator])]
@@ -116,7 +123,8 @@ mod app {
         let mut granulator = *ctx.shared.granulator;
 
         granulator.lock(|granulator| {
-            granulator.update_schedular(YOUR_TIME_INTERVAL);
+            // optional, the audio callback already starts and removes all grains
+            granulator.update_scheduler(YOUR_TIME_INTERVAL);
 
             // set all the parameters
         });
@@ -130,22 +138,48 @@ mod app {
 #![cfg_attr(feature = "no_std", no_std)]
 // #![warn(missing_docs)]
 
+#[cfg(feature = "std")]
+extern crate std;
+
 pub(crate) mod manager;
 
 pub(crate) mod audio_tools;
 pub(crate) mod grain;
 pub(crate) mod grains_vector;
+pub(crate) mod live_buffer;
+pub(crate) mod mip_map;
 pub(crate) mod pitch;
 pub(crate) mod pointer_wrapper;
+pub(crate) mod sample;
 pub(crate) mod scheduler;
 pub(crate) mod statistics;
+pub(crate) mod step_pattern;
+pub(crate) mod tempo_clock;
 pub(crate) mod user_settings;
+pub(crate) mod wav;
+pub(crate) mod window_table;
 
+pub use crate::manager::BufferSwap;
 pub use crate::manager::Granulator;
+pub use crate::manager::StaticGranulator;
+pub use crate::manager::MAX_CHANNELS;
 pub use crate::manager::MAX_GRAINS;
+pub use crate::manager::{GrainId, GrainState, SpawnError};
+pub use crate::mip_map::MAX_MIP_LEVELS;
+pub use crate::scheduler::EmissionMode;
+pub use crate::step_pattern::{Step, MAX_STEPS};
+pub use crate::tempo_clock::NoteDivision;
 
 pub use crate::user_settings::UserSettings;
 
-pub use crate::grain::WindowFunction;
+pub use crate::audio_tools::interpolation::Interpolation;
+pub use crate::grain::{ChannelMode, GrainSpec, WindowFunction};
+pub use crate::window_table::{Breakpoint, CustomWindow};
+
+pub use crate::sample::{Sample, I24};
 
 pub use crate::pitch::{ModeType, ScaleType};
+
+#[cfg(feature = "std")]
+pub use crate::wav::{decode_wav, load_wav, read_wav, WavBuffer};
+pub use crate::wav::{parse_wav, WavError, WavSample, WavSampleFormat, WavSpec, WavView};
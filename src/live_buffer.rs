@@ -0,0 +1,225 @@
+use super::pointer_wrapper::BufferSlice;
+
+use crate::sample::Sample;
+
+/// Circular buffer which is continuously filled with live input, while grains are reading behind
+/// its write head.
+#[derive(Debug)]
+pub struct LiveBuffer<T: Sample> {
+    buffer: BufferSlice<T>,
+    write_position: usize,
+
+    // biggest amount of samples pushed at once, the write head can move this far ahead of the
+    // sample that is currently being rendered. It is forgotten once the buffer has been written
+    // all the way around twice, so a single oversized push doesn't shrink the readable space for
+    // good.
+    largest_push: usize,
+    recent_push: usize, // biggest push since the buffer was last written all the way around
+    pushed: usize,      // samples pushed since then
+
+    // freeze
+    frozen: bool,
+    crossfade_length: usize,
+    crossfade_position: usize, // crossfade is done when it reaches its length
+}
+
+impl<T: Sample> LiveBuffer<T> {
+    pub fn from_mut_slice(slice: &mut [T]) -> Self {
+        LiveBuffer {
+            buffer: BufferSlice::from_mut_slice(slice),
+            write_position: 0,
+            largest_push: 1,
+            recent_push: 1,
+            pushed: 0,
+
+            frozen: false,
+            crossfade_length: 0,
+            crossfade_position: 0,
+        }
+    }
+
+    pub fn length(&self) -> usize {
+        self.buffer.length
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Stops writing into the buffer, so the captured material stays untouched.
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    /// Continues writing into the buffer. The incoming samples are being faded in over the
+    /// frozen material for `crossfade_length` samples.
+    pub fn unfreeze(&mut self, crossfade_length: usize) {
+        if self.frozen {
+            self.frozen = false;
+            self.crossfade_length = crossfade_length;
+            self.crossfade_position = 0;
+        }
+    }
+
+    /// Writes the given samples at the write head and moves it forward. Nothing is being written
+    /// while the buffer is frozen.
+    pub fn push(&mut self, samples: &[T]) {
+        if self.length() == 0 || self.frozen {
+            return;
+        }
+
+        for sample in samples {
+            let sample = if self.crossfade_position < self.crossfade_length {
+                self.crossfade_position += 1;
+                let gain = self.crossfade_position as f32 / self.crossfade_length as f32;
+                let frozen = self
+                    .buffer
+                    .get_f32_value_at(&mut self.write_position.clone(), 0);
+
+                T::from_f32(frozen + (sample.to_f32() - frozen) * gain)
+            } else {
+                *sample
+            };
+
+            // the write position always lies within the buffer
+            unsafe { self.buffer.ptr.write(self.write_position, sample) };
+
+            self.write_position += 1;
+            if self.write_position >= self.length() {
+                self.write_position = 0;
+            }
+        }
+
+        self.largest_push = self.largest_push.max(samples.len());
+        self.recent_push = self.recent_push.max(samples.len());
+        self.pushed += samples.len();
+        if self.pushed >= self.length() {
+            self.largest_push = self.recent_push;
+            self.recent_push = 1;
+            self.pushed = 0;
+        }
+    }
+
+    /// Returns a slice of `length` samples, which starts `delay` samples behind the write head.
+    ///
+    /// Delay and length are being truncated, so that the slice lies completely behind the write
+    /// head (the delay is at least the length) and doesn't get overwritten before a grain of the
+    /// same length finished playing.
+    pub fn get_sub_slice_behind_write_head(
+        &self,
+        delay: &mut usize,
+        length: &mut usize,
+    ) -> BufferSlice<T> {
+        // space that is neither being written during the grain nor by a whole push
+        let readable = self.length().saturating_sub(self.largest_push + 1);
+
+        // a grain plays as long as its slice, so the write head moves forward by its length
+        *length = (*length).min(readable / 2);
+        *delay = (*delay).clamp(*length, readable - *length);
+
+        let start = self.write_position + self.length() - *delay;
+        self.buffer.get_wrapping_sub_slice(start, *length)
+    }
+
+    /// Returns the whole buffer, starting at its beginning in memory.
+    pub fn get_buffer_slice(&self) -> BufferSlice<T> {
+        self.buffer.get_wrapping_sub_slice(0, self.length())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_and_wrap_around() {
+        let mut buffer = [0_f32; 4];
+        let mut l = LiveBuffer::from_mut_slice(&mut buffer);
+
+        l.push(&[1.0, 2.0, 3.0]);
+        assert!(l.write_position == 3);
+
+        l.push(&[4.0, 5.0]);
+        assert!(l.write_position == 1);
+
+        let slice = l.get_buffer_slice();
+        let values = [0, 1, 2, 3].map(|mut i| slice.get_f32_value_at(&mut i, 0));
+        assert!(values == [5.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn freeze_and_crossfade() {
+        let mut buffer = [0_f32; 8];
+        let mut l = LiveBuffer::from_mut_slice(&mut buffer);
+
+        l.freeze();
+        l.push(&[1.0; 4]);
+        assert!(l.write_position == 0);
+
+        l.unfreeze(4);
+        l.push(&[1.0; 6]);
+        assert!(l.write_position == 6);
+
+        let slice = l.get_buffer_slice();
+        let values = [0, 1, 2, 3, 4, 5, 6].map(|mut i| slice.get_f32_value_at(&mut i, 0));
+        assert!(values == [0.25, 0.5, 0.75, 1.0, 1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn read_behind_write_head() {
+        let mut buffer = [0_f32; 100];
+        let mut l = LiveBuffer::from_mut_slice(&mut buffer);
+
+        for i in 0..150 {
+            l.push(&[i as f32]);
+        }
+
+        let (mut delay, mut length) = (20, 10);
+        let slice = l.get_sub_slice_behind_write_head(&mut delay, &mut length);
+
+        assert!(delay == 20 && length == 10);
+        assert!(slice.get_f32_value_at(&mut 0, 0) == 130.0);
+        assert!(slice.get_f32_value_at(&mut 9, 0) == 139.0);
+    }
+
+    #[test]
+    fn never_cross_the_write_head() {
+        let mut buffer = [0_f32; 100];
+        let mut l = LiveBuffer::from_mut_slice(&mut buffer);
+        l.push(&[0.0; 8]);
+
+        // too close to the write head
+        let (mut delay, mut length) = (5, 10);
+        l.get_sub_slice_behind_write_head(&mut delay, &mut length);
+        assert!(delay == 10 && length == 10);
+
+        // too far away, the write head would catch up
+        let (mut delay, mut length) = (95, 10);
+        l.get_sub_slice_behind_write_head(&mut delay, &mut length);
+        assert!(delay + length + 8 < 100);
+
+        // too long to fit at all
+        let (mut delay, mut length) = (50, 80);
+        l.get_sub_slice_behind_write_head(&mut delay, &mut length);
+        assert!(delay >= length && delay + length + 8 < 100);
+    }
+
+    #[test]
+    fn forget_an_oversized_push() {
+        let mut buffer = [0_f32; 100];
+        let mut l = LiveBuffer::from_mut_slice(&mut buffer);
+
+        l.push(&[0.0; 40]);
+        let (mut delay, mut length) = (50, 50);
+        l.get_sub_slice_behind_write_head(&mut delay, &mut length);
+        assert!(length == 29);
+
+        // the readable space grows back once the buffer has been written around twice
+        for _ in 0..200 {
+            l.push(&[0.0]);
+        }
+        let (mut delay, mut length) = (50, 50);
+        l.get_sub_slice_behind_write_head(&mut delay, &mut length);
+        assert!(length == 49);
+    }
+}
@@ -5,25 +5,40 @@ use heapless::Vec;
 use oorandom::Rand32;
 
 // math
+#[cfg(feature = "no_std")]
+#[allow(unused_imports)]
 use micromath::F32Ext;
 
 // scheduler specific
-use super::scheduler::Scheduler;
+use super::scheduler::{get_onset_interval, EmissionMode, Scheduler, TimeInfo};
+use super::step_pattern::{Step, StepPattern};
+use super::tempo_clock::NoteDivision;
+use core::marker::PhantomData;
+use core::ops::Range;
 use core::time::Duration;
 
+#[cfg(feature = "std")]
+use std::sync::Arc;
+
 // crate specific
-use crate::grain::WindowFunction;
+use crate::grain::{ChannelMode, GrainParameters, GrainSpec, WindowFunction};
 use crate::grains_vector::GrainsVector;
+use crate::live_buffer::LiveBuffer;
 use crate::manager::GranulatorParameter::*;
+use crate::mip_map::{MipMap, MipMapBuilder};
 use crate::pointer_wrapper::BufferSlice;
+use crate::sample::Sample;
 use crate::statistics::*;
 use crate::user_settings::{GranulatorParameter, UserSettings};
+use crate::wav::{WavError, WavSample, WavSpec, WavView};
+use crate::window_table::{CustomWindow, WindowTable};
 
 // pitch specific
 use crate::pitch::{self, ScaleType};
 use crate::pitch::{ModeType, Ratio};
 
 // audio processing
+use super::audio_tools::interpolation::Interpolation;
 use super::audio_tools::soft_clip;
 
 /// The most grains grains that can possibly play at the same time.
@@ -32,6 +47,18 @@ use super::audio_tools::soft_clip;
 /// crate with a different number. This will change in the future.
 pub const MAX_GRAINS: usize = 50;
 
+/// The most channels an interleaved audio buffer can have.
+pub const MAX_CHANNELS: usize = 8;
+
+/// Time in milliseconds over which live input fades back in after releasing the freeze
+const FREEZE_CROSSFADE_MS: usize = 10;
+
+/// Time in milliseconds over which a cancelled grain fades out
+const CANCEL_FADE_MS: usize = 5;
+
+/// Frames of a pending mip map that are being filtered per rendered sample
+const MIP_MAP_FRAMES_PER_SAMPLE: usize = 8;
+
 /// Smallest value at which the spreading algorithm should be activated
 ///
 /// The ADC of the Electrosmith Daisy Seed (STM32h750) has a resolultion of 12 bit, so the
@@ -49,6 +76,7 @@ pub struct Parameters {
     pub pitch: f32,
     pub delay: Duration,
     pub velocity: f32,
+    pub pan: f32,
 
     // spread parameters
     pub sp_offset: f32,
@@ -56,22 +84,100 @@ pub struct Parameters {
     pub sp_pitch: f32,
     pub sp_delay: f32,
     pub sp_velocity: f32,
+    pub sp_pan: f32,
+
+    // grain emission
+    pub emission_mode: EmissionMode,
+    pub auto_emission: bool, // grains are only started by triggers if `false`
+    pub density: f32,        // grains per second
+    pub sp_density: f32,
+    pub jitter: f32, // only used in the quasi-synchronous emission mode
 
     // window function parameters
     pub window_function: WindowFunction,
     pub window_param: f32,
+    pub window_skew: f32,
+    pub sp_window_skew: f32,
+    pub morph_target: Option<WindowFunction>, // morphing is off if `None`
+    pub window_morph: f32,
+    pub exact_windows: bool, // computes every envelope value instead of reading the window table
 
     // musical pitch and scales
     pub scale: pitch::ScaleType,
     pub mode: pitch::ModeType,
+
+    // multi-channel audio buffers
+    pub channel_mode: ChannelMode,
+
+    // reading between samples
+    pub interpolation: Interpolation,
+
+    // playback direction
+    pub reverse_probability: f32,
+    pub ping_pong: bool,
+
+    // swapping audio buffers
+    pub buffer_swap: BufferSwap,
+}
+
+/// Identifies a grain started with `Granulator::spawn_grain`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GrainId(usize);
+
+/// Where a grain started with `Granulator::spawn_grain` is in its life
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrainState {
+    /// Waiting for its start
+    Scheduled,
+    /// Playing, including fading out
+    Playing,
+    /// Finished, cancelled or skipped because all voices were playing when it was due
+    Gone,
+}
+
+/// Reasons why a grain couldn't be spawned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    /// There is no audio buffer to read from
+    NoAudioBuffer,
+    /// `MAX_GRAINS` grains are already waiting for their start
+    SchedulerFull,
+    /// The pitch is not a finite number above 0
+    InvalidPitch,
+    /// The length is 0
+    ZeroLength,
+}
+
+/// Decides what happens to playing grains when the audio buffer is being swapped out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferSwap {
+    /// Grains finish playing on the previous buffer
+    Finish,
+    /// Grains reading from the previous buffer fade out over the given time
+    FadeOut(Duration),
 }
 
 /// The brain of the granular synthesis algorithm.
+///
+/// The audio buffers can be stored in any `Sample` format (`f32` by default), which are being
+/// scaled to floating point values while playing. Borrowed audio buffers have to outlive the
+/// lifetime `'a` of the granulator.
 #[derive(Debug)]
-pub struct Granulator {
+pub struct Granulator<'a, T: Sample = f32> {
     scheduler: Scheduler,
-    grains: GrainsVector<f32>,
-    audio_buffer: Option<BufferSlice<f32>>, // points to the beginning of the buffer
+    grains: GrainsVector<T>,
+    audio_buffer: Option<BufferSlice<T>>, // points to the beginning of the buffer
+    live_buffer: Option<LiveBuffer<T>>,   // only set when granulating live input
+    mip_map: MipMap<T>,
+    mip_map_storage: Option<BufferSlice<T>>,
+    mip_map_builder: Option<MipMapBuilder<T>>, // replaces the mip map once it is done
+    buffer_lifetime: PhantomData<&'a [T]>,
+
+    // shared audio buffers, grains only point into them
+    #[cfg(feature = "std")]
+    shared_buffer: Option<Arc<[T]>>,
+    #[cfg(feature = "std")]
+    retired_buffers: std::vec::Vec<Arc<[T]>>, // swapped out, handed back once released
 
     // user configurable
     settings: Parameters,
@@ -82,6 +188,9 @@ pub struct Granulator {
     random_pitch_value: f32,
     random_delay_value: Duration,
     random_velocity_value: f32,
+    random_pan_value: f32,
+    random_window_skew_value: f32,
+    random_density_value: f32,
 
     // pitch related
     pitch_ratios: pitch::HeptatonicRatios,
@@ -94,10 +203,14 @@ pub struct Granulator {
     rng: Rand32,
 }
 
-impl Granulator {
+/// Granulator which only plays audio buffers that live for the whole program, i.e. `static`
+/// buffers in flash memory. It can be moved freely between threads and interrupts.
+pub type StaticGranulator<T = f32> = Granulator<'static, T>;
+
+impl<'a> Granulator<'a> {
     /**
-    Constructs the Granulator object. A sample frequency is required, which can be changed
-    during playback if wanted.
+    Constructs the Granulator object for `f32` audio buffers. A sample frequency is required,
+    which can be changed during playback if wanted.
 
     ## Example
 
@@ -106,6 +219,26 @@ impl Granulator {
     ```
     */
     pub fn new(fs: usize) -> Self {
+        Granulator::with_sample_type(fs)
+    }
+}
+
+impl<'a, T: Sample> Granulator<'a, T> {
+    /**
+    Constructs the Granulator object for audio buffers in any `Sample` format. A sample frequency
+    is required, which can be changed during playback if wanted.
+
+    ## Example
+
+    ```
+    // i.e. samples kept in flash memory as 16 bit integers
+    static SAMPLES: [i16; 4] = [0, 16_384, 0, -16_384];
+
+    let mut g = granulator::Granulator::<i16>::with_sample_type(48_000);
+    g.set_audio_buffer(&SAMPLES);
+    ```
+    */
+    pub fn with_sample_type(fs: usize) -> Self {
         // The seed of the of the PRNG is being determined by the derefence of the `seed` argument.
         // This results in a non-repeating sequence of random numbers every time the the program gets
         // restarted. No need to generate a new random seed.
@@ -116,10 +249,23 @@ impl Granulator {
         let mode = pitch::ModeType::Mixolydian;
         let pitch_ratios = pitch::get_ratios_for(scale, mode);
 
+        let mut grains = GrainsVector::new();
+        grains.update_window_tables(Some(WindowFunction::Sine), None, 0.0);
+
         Granulator {
             scheduler: Scheduler::new(),
-            grains: GrainsVector::new(),
+            grains,
             audio_buffer: None,
+            live_buffer: None,
+            mip_map: MipMap::new(),
+            mip_map_storage: None,
+            mip_map_builder: None,
+            buffer_lifetime: PhantomData,
+
+            #[cfg(feature = "std")]
+            shared_buffer: None,
+            #[cfg(feature = "std")]
+            retired_buffers: std::vec::Vec::new(),
 
             settings: Parameters {
                 master_volume: 1.0 / MAX_GRAINS as f32,
@@ -129,18 +275,38 @@ impl Granulator {
                 pitch: 1.0,
                 delay: Duration::ZERO,
                 velocity: 1.0,
+                pan: 0.5,
 
                 sp_offset: 0.0,
                 sp_grain_size: 0.0,
                 sp_pitch: 0.0,
                 sp_delay: 0.0,
                 sp_velocity: 0.0,
+                sp_pan: 0.0,
+
+                emission_mode: EmissionMode::Refill,
+                auto_emission: true,
+                density: 10.0,
+                sp_density: 0.0,
+                jitter: 0.0,
 
                 window_function: WindowFunction::Sine,
                 window_param: 0.0,
+                window_skew: 0.5,
+                sp_window_skew: 0.0,
+                morph_target: None,
+                window_morph: 0.0,
+                exact_windows: false,
 
                 scale,
                 mode,
+
+                channel_mode: ChannelMode::All,
+                interpolation: Interpolation::Linear,
+
+                reverse_probability: 0.0,
+                ping_pong: false,
+                buffer_swap: BufferSwap::Finish,
             },
 
             random_offset_value: 0,
@@ -148,6 +314,9 @@ impl Granulator {
             random_pitch_value: 1.0,
             random_delay_value: Duration::ZERO,
             random_velocity_value: 1.0,
+            random_pan_value: 0.5,
+            random_window_skew_value: 0.5,
+            random_density_value: 10.0,
 
             pitch_ratios,
 
@@ -171,15 +340,33 @@ impl Granulator {
         self.set_parameter(Pitch, settings.pitch);
         self.set_parameter(Delay, settings.delay);
         self.set_parameter(Velocity, settings.velocity);
+        self.set_parameter(Pan, settings.pan);
         self.set_parameter(OffsetSpread, settings.sp_offset);
         self.set_parameter(GrainSizeSpread, settings.sp_grain_size);
         self.set_parameter(PitchSpread, settings.sp_pitch);
         self.set_parameter(DelaySpread, settings.sp_delay);
         self.set_parameter(VelocitySpread, settings.sp_velocity);
+        self.set_parameter(PanSpread, settings.sp_pan);
+        self.set_parameter(Density, settings.density);
+        self.set_parameter(DensitySpread, settings.sp_density);
+        self.set_parameter(Jitter, settings.jitter);
+        match settings.emission_mode {
+            0 => self.set_emission_mode(EmissionMode::Refill),
+            1 => self.set_emission_mode(EmissionMode::Synchronous),
+            2 => self.set_emission_mode(EmissionMode::QuasiSynchronous),
+            3 => self.set_emission_mode(EmissionMode::Asynchronous),
+            4 => self.set_emission_mode(EmissionMode::Tempo),
+            _ => {}
+        }
         self.set_window_function(settings.window_function);
         self.set_parameter(WindowParam, settings.window_param);
+        self.set_parameter(WindowSkew, settings.window_skew);
+        self.set_parameter(WindowSkewSpread, settings.sp_window_skew);
+        self.set_parameter(WindowMorph, settings.window_morph);
         self.set_scale(settings.scale);
         self.set_mode(settings.mode);
+        self.set_parameter(ReverseProbability, settings.reverse_probability);
+        self.set_ping_pong(settings.ping_pong);
     }
 
     // ==========================
@@ -197,6 +384,8 @@ impl Granulator {
         }
     }
 
+    /// Selects the window function of newly started grains by its number, in the order of
+    /// [`WindowFunction`] (0 = `Sine` up to 14 = `Custom`). Other values are ignored.
     pub fn set_window_function(&mut self, value: u8) {
         match value {
             0 => self.settings.window_function = WindowFunction::Sine,
@@ -205,12 +394,445 @@ impl Granulator {
             3 => self.settings.window_function = WindowFunction::Gaussian,
             4 => self.settings.window_function = WindowFunction::Tukey,
             5 => self.settings.window_function = WindowFunction::Trapezodial,
+            6 => self.settings.window_function = WindowFunction::Blackman,
+            7 => self.settings.window_function = WindowFunction::BlackmanHarris,
+            8 => self.settings.window_function = WindowFunction::Nuttall,
+            9 => self.settings.window_function = WindowFunction::Kaiser,
+            10 => self.settings.window_function = WindowFunction::Welch,
+            11 => self.settings.window_function = WindowFunction::PlanckTaper,
+            12 => self.settings.window_function = WindowFunction::Percussive,
+            13 => self.settings.window_function = WindowFunction::Rectangular,
+            14 => self.settings.window_function = WindowFunction::Custom,
             _ => {}
         }
+        self.update_window_table();
 
         self.pitch_ratios = pitch::get_ratios_for(self.settings.scale, self.settings.mode);
     }
 
+    /**
+    Sets the envelope of grains playing the `Custom` window function, either as a table of values
+    or as a list of breakpoints. It replaces the previous one for all grains at once. Returns the
+    length of a table shorter than 2 values or the index of a breakpoint out of order as error.
+
+    ## Example
+
+    ```
+    use granulator::{Breakpoint, CustomWindow};
+
+    // fast attack, short sustain and a slow exponential release
+    let envelope = [
+        Breakpoint::new(0.0, 0.0, 0.0),
+        Breakpoint::new(0.05, 1.0, -4.0),
+        Breakpoint::new(0.3, 0.8, 0.0),
+        Breakpoint::new(1.0, 0.0, -6.0),
+    ];
+
+    let mut g = granulator::Granulator::new(48_000);
+    g.set_custom_window(CustomWindow::Breakpoints(&envelope)).unwrap();
+    g.set_window_function(14);
+    ```
+    */
+    pub fn set_custom_window(&mut self, custom_window: CustomWindow) -> Result<(), usize> {
+        custom_window.validate()?;
+        self.grains
+            .set_custom_window_table(WindowTable::from_custom(&custom_window));
+        Ok(())
+    }
+
+    /**
+    Lets newly started grains morph from the selected window function into the given one, by the
+    amount of the `WindowMorph` parameter at the time they start. Pass `None` to turn morphing
+    off again.
+
+    ## Example
+
+    ```
+    use granulator::{UserSettings, WindowFunction};
+
+    static SAMPLES: [f32; 4] = [0.0, 0.5, 0.0, -0.5];
+
+    let mut g = granulator::Granulator::new(48_000);
+    g.set_audio_buffer(&SAMPLES);
+
+    // a quarter of the way from a gaussian to a trapezoidal window
+    let mut settings = UserSettings::new_empty();
+    settings.window_function = 3;
+    settings.window_morph = 0.25;
+    g.update_all_user_settings(&settings);
+    g.set_window_morph(Some(WindowFunction::Trapezodial));
+    ```
+    */
+    pub fn set_window_morph(&mut self, morph_target: Option<WindowFunction>) {
+        self.settings.morph_target = morph_target;
+        self.update_window_table();
+    }
+
+    /// Computes the envelopes of all grains exactly for every sample if enabled. By default the
+    /// window function is rendered into a table once, whenever it or its parameter changes, which
+    /// grains read with linear interpolation. This saves a lot of CPU time on platforms without
+    /// hardware support for `sin`, `cos` and `exp`. Custom windows are always read from their
+    /// table.
+    pub fn set_exact_windows(&mut self, exact_windows: bool) {
+        self.settings.exact_windows = exact_windows;
+        self.update_window_table();
+    }
+
+    /// Sets which channels of a multi-channel audio buffer newly started grains are reading.
+    pub fn set_channel_mode(&mut self, channel_mode: ChannelMode) {
+        self.settings.channel_mode = channel_mode;
+    }
+
+    /// Lets newly started grains bounce back and forth between both ends of their source slice
+    /// instead of wrapping around, which is audible for grains longer than their source slice.
+    pub fn set_ping_pong(&mut self, ping_pong: bool) {
+        self.settings.ping_pong = ping_pong;
+    }
+
+    /// Sets how newly started grains are reading between two samples of the audio buffer.
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.settings.interpolation = interpolation;
+    }
+
+    /// Sets when new grains are started: whenever playing ones finish, at the rate of the density
+    /// or on the steps of the tempo clock (see `EmissionMode`). Switching to the `Tempo` mode
+    /// restarts the tempo clock.
+    pub fn set_emission_mode(&mut self, emission_mode: EmissionMode) {
+        if emission_mode == EmissionMode::Tempo && self.settings.emission_mode != emission_mode {
+            self.restart_tempo_clock();
+        }
+        self.settings.emission_mode = emission_mode;
+    }
+
+    // ===========
+    // TEMPO CLOCK
+    // ===========
+
+    /**
+    Sets the tempo in BPM (between 1 and 999) the steps of the tempo clock are counted from. The
+    grains start on the steps in the `Tempo` emission mode.
+
+    ## Example
+
+    ```
+    use granulator::{EmissionMode, NoteDivision};
+
+    let mut g = granulator::Granulator::new(48_000);
+    g.set_emission_mode(EmissionMode::Tempo);
+
+    // swung eighth note triplets at 92 BPM
+    g.set_tempo(92.0);
+    g.set_note_division(NoteDivision::Triplet(8));
+    g.set_swing(0.3);
+    ```
+    */
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.scheduler.tempo_clock.set_tempo(bpm);
+    }
+
+    /// Sets the length of the steps of the tempo clock.
+    pub fn set_note_division(&mut self, division: NoteDivision) {
+        self.scheduler.tempo_clock.set_division(division);
+    }
+
+    /// Delays every second step of the tempo clock by up to half a step (between 0 and 1).
+    pub fn set_swing(&mut self, swing: f32) {
+        self.scheduler.tempo_clock.set_swing(swing);
+    }
+
+    /**
+    Lets the tempo clock count its steps in ticks of an external clock with the given resolution
+    in pulses per quarter note (i.e. 24 for MIDI clock), instead of counting them from the tempo.
+    Every tick has to be passed on with `clock_tick`. Pass `None` to go back to the tempo.
+
+    Returns the resolution as an error if it is 0.
+
+    ## Example
+
+    ```
+    use granulator::EmissionMode;
+
+    let mut g = granulator::Granulator::new(48_000);
+    g.set_emission_mode(EmissionMode::Tempo);
+    g.set_external_clock(Some(24)).unwrap();
+
+    // on every incoming clock pulse of the host
+    g.clock_tick();
+    ```
+    */
+    pub fn set_external_clock(&mut self, ppqn: Option<u32>) -> Result<(), u32> {
+        self.scheduler.tempo_clock.set_external_clock(ppqn)?;
+        self.scheduler.clear_emissions();
+        self.scheduler.previous_tick = None;
+        Ok(())
+    }
+
+    /// Advances the external clock by a single tick. Grains start with the next rendered sample
+    /// if a step is due on it. Steps in between two ticks are placed by the time the previous two
+    /// ticks were apart. Does nothing unless the `Tempo` emission mode runs on an external clock.
+    pub fn clock_tick(&mut self) {
+        if self.settings.emission_mode != EmissionMode::Tempo {
+            return;
+        }
+
+        let clock = self.scheduler.master_clock_counter;
+        let previous_tick = self.scheduler.previous_tick.replace(clock);
+        let samples_per_tick = clock - previous_tick.unwrap_or(clock);
+
+        for offset in self.scheduler.tempo_clock.tick() {
+            self.schedule_tempo_step((offset * samples_per_tick as f32) as u64);
+        }
+    }
+
+    /// Starts the tempo clock over at its first step, which is due immediately, i.e. when the
+    /// transport of the host starts. The pattern starts over as well.
+    pub fn restart_tempo_clock(&mut self) {
+        self.scheduler.tempo_clock.restart();
+        if let Some(pattern) = &mut self.scheduler.pattern {
+            pattern.restart();
+        }
+        self.scheduler.clear_emissions();
+        self.scheduler.previous_tick = None;
+    }
+
+    // ========
+    // PATTERNS
+    // ========
+
+    /**
+    Lets grains start only on the hits of a Euclidean rhythm in the `Tempo` emission mode, which
+    spreads `hits` as evenly as possible across `steps` steps of the tempo clock and starts at the
+    step `rotation` of it. Returns the amount of steps as error if there are none or more than
+    `MAX_STEPS`.
+
+    The pattern starts at its first step with the next step of the tempo clock.
+
+    ## Example
+
+    ```
+    use granulator::{EmissionMode, NoteDivision};
+
+    let mut g = granulator::Granulator::new(48_000);
+    g.set_emission_mode(EmissionMode::Tempo);
+    g.set_note_division(NoteDivision::Straight(16));
+
+    // x..x..x. on sixteenth notes
+    g.set_euclidean_pattern(3, 8, 0).unwrap();
+    ```
+    */
+    pub fn set_euclidean_pattern(
+        &mut self,
+        hits: usize,
+        steps: usize,
+        rotation: usize,
+    ) -> Result<(), usize> {
+        self.scheduler.pattern = Some(StepPattern::euclidean(hits, steps, rotation)?);
+        Ok(())
+    }
+
+    /**
+    Lets grains start on the steps of a pattern in the `Tempo` emission mode, each with its own
+    probability and accent, which scales the velocity of its grain. Returns the amount of steps
+    as error if there are none or more than `MAX_STEPS`.
+
+    The pattern starts at its first step with the next step of the tempo clock.
+
+    ## Example
+
+    ```
+    use granulator::{EmissionMode, Step};
+
+    let mut g = granulator::Granulator::new(48_000);
+    g.set_emission_mode(EmissionMode::Tempo);
+
+    // an accented downbeat, followed by softer and sparser grains
+    let steps = [
+        Step::HIT,
+        Step::new(0.5, 0.3),
+        Step::new(0.8, 0.6),
+        Step::REST,
+    ];
+    g.set_step_pattern(&steps).unwrap();
+    ```
+    */
+    pub fn set_step_pattern(&mut self, steps: &[Step]) -> Result<(), usize> {
+        self.scheduler.pattern = Some(StepPattern::new(steps)?);
+        Ok(())
+    }
+
+    /// Removes the pattern, so grains start on every step of the tempo clock again.
+    pub fn clear_pattern(&mut self) {
+        self.scheduler.pattern = None;
+    }
+
+    // ========
+    // TRIGGERS
+    // ========
+
+    /**
+    Starts a grain with the next rendered sample, with the current parameters and spreads. Returns
+    the amount of grains that couldn't be scheduled as error.
+
+    Triggers work alongside the emission mode. Turn the automatic emission off with
+    `set_auto_emission` to start grains only by triggers.
+
+    ## Example
+
+    ```
+    static SAMPLES: [f32; 4] = [0.0, 0.5, 0.0, -0.5];
+
+    let mut g = granulator::Granulator::new(48_000);
+    g.set_audio_buffer(&SAMPLES);
+    g.set_auto_emission(false);
+
+    // on every rising edge of the gate input
+    g.trigger().ok();
+    ```
+    */
+    pub fn trigger(&mut self) -> Result<(), usize> {
+        self.trigger_at_sample(0)
+    }
+
+    /// Starts a burst of `count` grains with the next rendered sample, each with its own random
+    /// values of the spreads. Returns the amount of grains that couldn't be scheduled as error.
+    pub fn trigger_burst(&mut self, count: usize) -> Result<(), usize> {
+        for scheduled in 0..count {
+            if self.schedule_triggered_grain(0).is_err() {
+                return Err(count - scheduled);
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts a grain on the given sample from now, i.e. on the exact sample within the next
+    /// rendered block a drum hit has been detected on. Returns the amount of grains that couldn't
+    /// be scheduled as error.
+    pub fn trigger_at_sample(&mut self, sample: usize) -> Result<(), usize> {
+        self.schedule_triggered_grain(sample as u64)
+    }
+
+    /// Starts grains automatically, depending on the emission mode, if enabled (the default).
+    /// Otherwise grains are only started by triggers and external clock ticks.
+    pub fn set_auto_emission(&mut self, auto_emission: bool) {
+        self.settings.auto_emission = auto_emission;
+    }
+
+    /**
+    Starts a single grain with explicit parameters instead of randomly spread ones, after the
+    delay of the spec. Returns its id, or an error if there is no audio buffer, no more grains
+    can be scheduled or the spec can't be played. The grain is skipped if all voices are playing
+    once it is due.
+
+    The id can be passed to `get_grain_state` and `cancel_grain`. Skipped grains are `Gone`.
+
+    ## Example
+
+    ```
+    use core::time::Duration;
+    use granulator::{GrainSpec, WindowFunction};
+
+    static SAMPLES: [f32; 48_000] = [0.0; 48_000];
+
+    let mut g = granulator::Granulator::new(48_000);
+    g.set_audio_buffer(&SAMPLES);
+    g.set_auto_emission(false);
+
+    // an arpeggio of three grains, a fifth and an octave apart
+    for (i, pitch) in [1.0, 1.5, 2.0].into_iter().enumerate() {
+        let spec = GrainSpec {
+            offset: 12_000,
+            length: 4_800,
+            pitch,
+            window: WindowFunction::Hann,
+            delay: Duration::from_millis(100 * i as u64),
+            ..Default::default()
+        };
+        g.spawn_grain(spec).unwrap();
+    }
+    ```
+    */
+    pub fn spawn_grain(&mut self, spec: GrainSpec) -> Result<GrainId, SpawnError> {
+        if self.audio_buffer.is_none() {
+            return Err(SpawnError::NoAudioBuffer);
+        }
+        if !spec.pitch.is_finite() || spec.pitch <= 0.0 {
+            return Err(SpawnError::InvalidPitch);
+        }
+        if spec.length == 0 {
+            return Err(SpawnError::ZeroLength);
+        }
+        if self.scheduler.future_vector.is_full() {
+            return Err(SpawnError::SchedulerFull);
+        }
+
+        let id = self.get_new_id();
+        let delay = self.duration_in_samples(spec.delay);
+        self.scheduler
+            .schedule_grain_with_spec(id, delay, spec)
+            .ok();
+
+        Ok(GrainId(id))
+    }
+
+    /// Returns whether a grain started with `spawn_grain` is waiting for its start, playing or
+    /// gone.
+    pub fn get_grain_state(&self, id: GrainId) -> GrainState {
+        let scheduled = self
+            .scheduler
+            .future_vector
+            .iter()
+            .any(|time_info| time_info.id == id.0);
+        let playing = self
+            .grains
+            .get_grains()
+            .iter()
+            .any(|grain| grain.id == id.0 && !grain.finished);
+
+        match (scheduled, playing) {
+            (true, _) => GrainState::Scheduled,
+            (false, true) => GrainState::Playing,
+            (false, false) => GrainState::Gone,
+        }
+    }
+
+    /// Cancels a grain started with `spawn_grain`. A scheduled grain never starts, a playing one
+    /// fades out within a few milliseconds. Returns the id as error if the grain is already gone.
+    pub fn cancel_grain(&mut self, id: GrainId) -> Result<(), GrainId> {
+        if self.scheduler.remove_grain(id.0).is_ok() {
+            return Ok(());
+        }
+
+        let fade_length = self.fs * CANCEL_FADE_MS / 1000;
+        match self
+            .grains
+            .get_mut_grains()
+            .iter_mut()
+            .find(|grain| grain.id == id.0 && !grain.finished)
+        {
+            Some(grain) => {
+                grain.fade_out(fade_length);
+                Ok(())
+            }
+            None => Err(id),
+        }
+    }
+
+    /// Schedules a single grain, unless the scheduler is full.
+    fn schedule_triggered_grain(&mut self, delay: u64) -> Result<(), usize> {
+        if self.scheduler.future_vector.is_full() {
+            return Err(1);
+        }
+
+        let id = self.get_new_id();
+        self.scheduler.schedule_grain(id, delay).ok();
+        Ok(())
+    }
+
+    /// Sets what happens to playing grains when the audio buffer is being swapped out.
+    pub fn set_buffer_swap(&mut self, buffer_swap: BufferSwap) {
+        self.settings.buffer_swap = buffer_swap;
+    }
+
     pub fn set_scale(&mut self, value: u8) {
         match value {
             0 => self.settings.scale = ScaleType::Diatonic,
@@ -239,43 +861,90 @@ impl Granulator {
     /// Sets a `GranulatorParameter` with bound checking. If the given value is less than 0, it will
     /// be kept at 0. If the the given value is more than 1, it will be kept at 1.
     pub fn set_parameter(&mut self, parameter: GranulatorParameter, value: f32) {
-        if self.audio_buffer.is_some() {
+        if let Some(audio_buffer) = &self.audio_buffer {
             let parameter_value = value.clamp(0.0, 1.0);
+            let buffer_length = audio_buffer.length;
 
             match parameter {
                 ActiveGrains => {
-                    self.settings.active_grains = (parameter_value * MAX_GRAINS as f32) as usize;
+                    self.set_active_grains((parameter_value * MAX_GRAINS as f32) as usize);
                 }
                 Offset => {
-                    self.settings.offset = (parameter_value
-                        * self.audio_buffer.as_ref().unwrap().length as f32)
-                        as usize;
-                }
-                GrainSize => {
-                    let size_in_ms = parameter_value * 1000.0;
-                    let size_in_samples = ((self.fs as f32 / 1000.0) * size_in_ms) as usize;
-                    let max_length =
-                        self.audio_buffer.as_ref().unwrap().length as usize - self.settings.offset;
-                    if size_in_samples >= max_length {
-                        self.settings.grain_size_in_samples = max_length;
-                    } else {
-                        self.settings.grain_size_in_samples = size_in_samples;
-                    }
+                    self.settings.offset = (parameter_value * buffer_length as f32) as usize;
                 }
-                Pitch => self.settings.pitch = 10.0.powf(parameter_value * 2.0 - 1.0),
+                GrainSize => self.set_grain_size(parameter_value * 1000.0),
+                Pitch => self.settings.pitch = 10.0_f32.powf(parameter_value * 2.0 - 1.0),
                 Delay => self.settings.delay = Duration::from_secs((parameter_value) as u64),
                 Velocity => self.settings.velocity = parameter_value,
-                MasterVolume => self.settings.master_volume = parameter_value,
+                Pan => self.settings.pan = parameter_value,
+                MasterVolume => self.set_master_volume(parameter_value),
                 OffsetSpread => self.settings.sp_offset = parameter_value,
                 GrainSizeSpread => self.settings.sp_grain_size = parameter_value,
                 PitchSpread => self.settings.sp_pitch = parameter_value,
                 DelaySpread => self.settings.sp_delay = parameter_value,
                 VelocitySpread => self.settings.sp_velocity = parameter_value,
-                WindowParam => self.settings.window_param = parameter_value,
+                PanSpread => self.settings.sp_pan = parameter_value,
+                Density => self.set_density(10.0_f32.powf(parameter_value * 3.0)),
+                DensitySpread => self.settings.sp_density = parameter_value,
+                Jitter => self.settings.jitter = parameter_value,
+                WindowParam => {
+                    self.settings.window_param = parameter_value;
+                    self.update_window_table();
+                }
+                WindowSkew => self.settings.window_skew = parameter_value,
+                WindowSkewSpread => self.settings.sp_window_skew = parameter_value,
+                WindowMorph => self.settings.window_morph = parameter_value,
+                ReverseProbability => self.settings.reverse_probability = parameter_value,
             }
         }
     }
 
+    /// Sets the amount of grains that should be playing at the same time. Values above
+    /// `MAX_GRAINS` will be kept at `MAX_GRAINS`. In the emission modes driven by the density this
+    /// is only the upper limit of grains playing at once.
+    pub fn set_active_grains(&mut self, active_grains: usize) {
+        self.settings.active_grains = active_grains.min(MAX_GRAINS);
+    }
+
+    /// Sets how many grains per second are started on average in the emission modes driven by
+    /// the density, between 0.1 and 1000. The next grain is moved to the new rate right away.
+    pub fn set_density(&mut self, grains_per_second: f32) {
+        let density = grains_per_second.clamp(0.1, 1000.0);
+        if density == self.settings.density {
+            return;
+        }
+
+        // the pending grain of a low density would keep the new one waiting
+        if let EmissionMode::Synchronous
+        | EmissionMode::QuasiSynchronous
+        | EmissionMode::Asynchronous = self.settings.emission_mode
+        {
+            self.scheduler
+                .stretch_emissions(self.settings.density / density);
+        }
+        self.settings.density = density;
+    }
+
+    /// Sets the grain size in milliseconds. The grain size can never exceed the space between the
+    /// current offset and the end of the audio buffer.
+    pub fn set_grain_size(&mut self, size_in_ms: f32) {
+        if let Some(audio_buffer) = &self.audio_buffer {
+            let size_in_samples = ((self.fs as f32 / 1000.0) * size_in_ms.max(0.0)) as usize;
+            // a live offset is the delay behind the write head, not the start of the grain
+            let max_length = if self.live_buffer.is_some() {
+                audio_buffer.length
+            } else {
+                audio_buffer.length - self.settings.offset
+            };
+            self.settings.grain_size_in_samples = size_in_samples.min(max_length);
+        }
+    }
+
+    /// Sets the master volume with bound checking between 0 and 1.
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.settings.master_volume = volume.clamp(0.0, 1.0);
+    }
+
     // ==============
     // AUDIO CALLBACK
     // ==============
@@ -308,95 +977,699 @@ impl Granulator {
     */
     pub fn get_next_sample(&mut self) -> f32 {
         if self.audio_buffer.is_some() {
-            soft_clip(self.grains.get_next_sample() * self.settings.master_volume)
+            let mut sample = 0.0;
+            self.render_segments(1, |grains, _| sample = grains.get_next_sample());
+            soft_clip(sample * self.settings.master_volume)
         } else {
             0.0
         }
     }
 
-    pub fn get_sample_rate(&self) -> usize {
-        self.fs
-    }
+    /**
+    Returns a cummulated stereo frame `(left, right)` of all grains with master volume and soft
+    clipping applied. Every grain is placed in the stereo field according to its own pan position.
 
-    // ========================
-    // AUDIO BUFFER INTERACTION
-    // ========================
+    Use this in the audio callback instead of `get_next_sample` for stereo output.
 
-    /// Sets a new audio buffer for the algorithm to work on.
-    pub fn set_audio_buffer(&mut self, buffer: &[f32]) {
-        // create slice buffer
-        self.audio_buffer = Some(BufferSlice::from_slice(buffer));
-    }
+    ## Example
 
-    // ====================
-    // SCHEDULER MANAGEMENT
-    // ====================
+    ```
+    let mut g = granulator::Granulator::new(48_000);
 
-    /// Updates the internal scheduler which keeps track of which grain will be started/triggered
-    /// at which point in time. It also removes every grain, that has finished playing.
-    ///
-    /// This should be updated in a dedicated update task/thread in regular intervals < 20ms.
-    /// Preferrably at the end.
-    pub fn update_scheduler(&mut self, time_step: Duration) {
+    let mut left = [0_f32; 64];
+    let mut right = [0_f32; 64];
+    for i in 0..left.len() {
+        (left[i], right[i]) = g.get_next_frame();
+    }
+    ```
+    */
+    pub fn get_next_frame(&mut self) -> (f32, f32) {
         if self.audio_buffer.is_some() {
-            self.spawn_future_grains();
-            let ids = self.scheduler.update_clock(time_step);
-            self.activate_grains(&ids);
-            self.remove_finished_grains();
+            let (mut left, mut right) = (0.0, 0.0);
+            self.render_segments(1, |grains, _| (left, right) = grains.get_next_frame());
+            (
+                soft_clip(left * self.settings.master_volume),
+                soft_clip(right * self.settings.master_volume),
+            )
+        } else {
+            (0.0, 0.0)
         }
     }
 
-    fn spawn_future_grains(&mut self) {
-        // the difference between active grains parameter and already spawned grains, but never less than zero
-        let to_be_spawned = self
-            .settings
-            .active_grains
-            .checked_sub(self.grains.get_grains().len())
-            .unwrap_or(0);
+    /**
+    Fills the whole buffer with cummulated sample values of all grains with master volume and
+    soft clipping applied. The output is exactly the same as calling `get_next_sample` for every
+    sample of the buffer, but the grains are rendered block by block.
 
-        // spawn future grains
-        for _ in 0..to_be_spawned {
-            let id = self.get_new_id();
-            let delay = self.get_new_delay();
-            self.scheduler.schedule_grain(id, delay).ok();
-        }
-    }
+    Use this in the audio callback to lock the `Granulator` only once per buffer.
+
+    ## Example
+
+    ```
+    let mut g = granulator::Granulator::new(48_000);
 
-    fn activate_grains(&mut self, ids: &Vec<usize, MAX_GRAINS>) {
+    let mut buffer = [0_f32; 64];
+    g.process_block(&mut buffer);
+    ```
+    */
+    pub fn process_block(&mut self, buffer: &mut [f32]) {
         if self.audio_buffer.is_some() {
-            for id in ids {
-                let velocity = self.get_new_velocity();
-                let pitch = self.get_new_pitch();
-                let mut offset = self.get_new_offset();
-                let mut grain_size = self.get_new_grain_size() as usize;
-                self.grains
-                    .push_grain(
-                        *id,
-                        self.audio_buffer
-                            .as_ref()
-                            .unwrap()
-                            .get_sub_slice(&mut offset, &mut grain_size),
-                        self.get_new_window(),
-                        self.settings.window_param,
-                        pitch,
-                        velocity,
-                    )
-                    .unwrap();
+            self.render_segments(buffer.len(), |grains, range| {
+                grains.process_block(&mut buffer[range])
+            });
+            for sample in buffer.iter_mut() {
+                *sample = soft_clip(*sample * self.settings.master_volume);
             }
+        } else {
+            buffer.fill(0.0);
         }
     }
 
-    fn remove_finished_grains(&mut self) {
-        let grains_vector = self.grains.get_mut_grains();
+    /**
+    Stereo counterpart of `process_block`. Fills both channel buffers with the same output as
+    calling `get_next_frame` for every frame. If the buffers differ in length, only the frames
+    both of them can hold are being processed.
 
-        for i in (0..grains_vector.len()).rev() {
-            if grains_vector[i].finished {
-                grains_vector.swap_remove(i);
+    ## Example
+
+    ```
+    let mut g = granulator::Granulator::new(48_000);
+
+    let mut left = [0_f32; 64];
+    let mut right = [0_f32; 64];
+    g.process_stereo_block(&mut left, &mut right);
+    ```
+    */
+    pub fn process_stereo_block(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let frames = left.len().min(right.len());
+        let (left, right) = (&mut left[..frames], &mut right[..frames]);
+
+        if self.audio_buffer.is_some() {
+            self.render_segments(frames, |grains, range| {
+                grains.process_stereo_block(&mut left[range.clone()], &mut right[range])
+            });
+            for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+                *left = soft_clip(*left * self.settings.master_volume);
+                *right = soft_clip(*right * self.settings.master_volume);
             }
+        } else {
+            left.fill(0.0);
+            right.fill(0.0);
         }
     }
 
-    fn get_new_id(&mut self) -> usize {
+    pub fn get_sample_rate(&self) -> usize {
+        self.fs
+    }
+
+    /// Renders `frames` samples in segments. Grains are only started and removed in between two
+    /// segments, and every segment ends on the exact sample a grain has to start or finishes.
+    fn render_segments(
+        &mut self,
+        frames: usize,
+        mut render: impl FnMut(&mut GrainsVector<T>, Range<usize>),
+    ) {
+        self.build_mip_map(frames);
+
+        let mut start = 0;
+        while start < frames {
+            self.update_grains();
+
+            let end = start + self.samples_until_next_event().min(frames - start);
+            render(&mut self.grains, start..end);
+            self.scheduler.advance_clock((end - start) as u64);
+
+            start = end;
+        }
+    }
+
+    // ========================
+    // AUDIO BUFFER INTERACTION
+    // ========================
+
+    /**
+    Sets a new audio buffer for the algorithm to work on. The buffer stays borrowed as long as
+    the granulator lives.
+
+    ## Example
+
+    ```
+    let buffer = vec![0_f32; 48_000];
+
+    let mut g = granulator::Granulator::new(48_000);
+    g.set_audio_buffer(&buffer);
+    ```
+
+    A buffer that is dropped before the granulator is being rejected by the compiler.
+
+    ```compile_fail
+    let mut g = granulator::Granulator::new(48_000);
+    {
+        let buffer = vec![0_f32; 48_000];
+        g.set_audio_buffer(&buffer);
+    }
+    g.get_next_sample();
+    ```
+    */
+    pub fn set_audio_buffer(&mut self, buffer: &'a [T]) {
+        // create slice buffer
+        self.swap_audio_buffer(BufferSlice::from_slice(buffer), None);
+    }
+
+    /**
+    Sets a new interleaved audio buffer with the given amount of channels for the algorithm to
+    work on. Which channels each grain reads is determined by `set_channel_mode`.
+
+    Returns the amount of channels as an error if it is 0 or more than `MAX_CHANNELS`.
+
+    ## Example
+
+    ```
+    let mut g = granulator::Granulator::new(48_000);
+
+    // left, right, left, right, ...
+    let stereo_buffer = [0_f32; 2 * 48_000];
+    g.set_interleaved_audio_buffer(&stereo_buffer, 2).unwrap();
+    ```
+    */
+    pub fn set_interleaved_audio_buffer(
+        &mut self,
+        buffer: &'a [T],
+        channels: usize,
+    ) -> Result<(), usize> {
+        // the buffer is borrowed for the whole lifetime of the granulator
+        unsafe { self.set_audio_buffer_unchecked(buffer, channels) }
+    }
+
+    /**
+    Sets an interleaved audio buffer of any lifetime for the algorithm to work on, the same way
+    `set_interleaved_audio_buffer` does.
+
+    Returns the amount of channels as an error if it is 0 or more than `MAX_CHANNELS`.
+
+    # Safety
+    The buffer has to stay alive and unchanged until the granulator is dropped, or until every
+    grain reading from it has finished after another buffer has been set.
+    */
+    pub unsafe fn set_audio_buffer_unchecked(
+        &mut self,
+        buffer: &[T],
+        channels: usize,
+    ) -> Result<(), usize> {
+        if channels == 0 || channels > MAX_CHANNELS {
+            return Err(channels);
+        }
+
+        self.swap_audio_buffer(BufferSlice::from_interleaved_slice(buffer, channels), None);
+        Ok(())
+    }
+
+    /**
+    Sets a reference counted interleaved audio buffer for the algorithm to work on, so it can be
+    swapped out at any time without borrowing it. The granulator keeps the buffer alive while
+    grains are still reading from it after it has been swapped out, and hands it back with
+    `take_released_buffer` afterwards, so it is never freed on the audio thread.
+
+    Returns the amount of channels as an error if it is 0 or more than `MAX_CHANNELS`.
+
+    ## Example
+
+    ```
+    use std::sync::Arc;
+
+    let mut g = granulator::Granulator::new(48_000);
+
+    let buffer: Arc<[f32]> = vec![0.0; 48_000].into();
+    g.set_shared_audio_buffer(buffer, 1).unwrap();
+    ```
+    */
+    #[cfg(feature = "std")]
+    pub fn set_shared_audio_buffer(
+        &mut self,
+        buffer: Arc<[T]>,
+        channels: usize,
+    ) -> Result<(), usize> {
+        if channels == 0 || channels > MAX_CHANNELS {
+            return Err(channels);
+        }
+
+        self.swap_audio_buffer(BufferSlice::from_interleaved_slice(&buffer, channels), None);
+        self.shared_buffer = Some(buffer);
+        Ok(())
+    }
+
+    /**
+    Hands back a shared audio buffer which has been swapped out and isn't read by any grain
+    anymore, so it can be dropped or reused outside of the audio thread. Returns `None` if there
+    is none. Buffers that are never taken are kept until the granulator is dropped.
+
+    ## Example
+
+    ```
+    let mut g = granulator::Granulator::new(48_000);
+    g.set_shared_audio_buffer(vec![0.0; 48_000].into(), 1).unwrap();
+    g.set_shared_audio_buffer(vec![0.0; 48_000].into(), 1).unwrap();
+
+    // on the control thread
+    while let Some(buffer) = g.take_released_buffer() {
+        drop(buffer);
+    }
+    ```
+    */
+    #[cfg(feature = "std")]
+    pub fn take_released_buffer(&mut self) -> Option<Arc<[T]>> {
+        let grains = self.grains.get_grains();
+        let index = self.retired_buffers.iter().position(|buffer| {
+            let slice = BufferSlice::from_slice(buffer);
+            grains
+                .iter()
+                .all(|grain| grain.finished || !grain.reads_from(&slice))
+        })?;
+
+        Some(self.retired_buffers.swap_remove(index))
+    }
+
+    /**
+    Sets the data chunk of a WAV file in memory as the audio buffer, without copying it. This
+    allows playing WAV files straight from memory mapped flash.
+
+    The sample type of the granulator has to match the format of the file. Returns the format of
+    the file, so its sample rate can be applied as well.
+
+    ## Example
+
+    ```
+    use granulator::Granulator;
+
+    // i.e. a 16 bit file memory mapped from external flash
+    fn load_sample(g: &mut Granulator<i16>, flash: &'static [u8]) {
+        let spec = g.set_wav_audio_buffer(flash).unwrap();
+        g.set_sample_rate(spec.sample_rate).unwrap();
+    }
+    ```
+    */
+    pub fn set_wav_audio_buffer(&mut self, wav: &'a [u8]) -> Result<WavSpec, WavError>
+    where
+        T: WavSample,
+    {
+        let view = WavView::parse(wav)?;
+        self.set_interleaved_audio_buffer(view.samples::<T>()?, view.spec.channels)
+            .map_err(WavError::TooManyChannels)?;
+        Ok(view.spec)
+    }
+
+    /**
+    Sets a circular buffer, which is being filled with live input by `push_live_input`, as the
+    source of the algorithm. Grains are reading behind its write head and the `Offset` parameter
+    becomes the delay time between the write head and the start of a grain.
+
+    Grains never read across the write head. Grain sizes and delays are truncated, so that each
+    grain has been written completely before it starts and isn't overwritten while playing.
+
+    ## Example
+
+    ```
+    let mut g = granulator::Granulator::new(48_000);
+    let mut ring_buffer = [0_f32; 48_000];
+    g.set_live_buffer(&mut ring_buffer);
+
+    // audio callback
+    let input = [0_f32; 64];
+    let mut output = [0_f32; 64];
+    g.push_live_input(&input);
+    g.process_block(&mut output);
+    ```
+    */
+    pub fn set_live_buffer(&mut self, buffer: &'a mut [T]) {
+        let live_buffer = LiveBuffer::from_mut_slice(buffer);
+        self.swap_audio_buffer(live_buffer.get_buffer_slice(), Some(live_buffer));
+    }
+
+    /// Replaces the source of the algorithm. Grains which are still reading from the previous
+    /// buffer either finish playing or fade out, depending on the `BufferSwap` mode.
+    fn swap_audio_buffer(&mut self, buffer: BufferSlice<T>, live_buffer: Option<LiveBuffer<T>>) {
+        if let BufferSwap::FadeOut(fade_time) = self.settings.buffer_swap {
+            let fade_length = self.duration_in_samples(fade_time) as usize;
+            for grain in self.grains.get_mut_grains() {
+                if !grain.reads_from(&buffer) {
+                    grain.fade_out(fade_length);
+                }
+            }
+        }
+
+        // the previous shared buffer must not be freed while grains are reading from it
+        #[cfg(feature = "std")]
+        if let Some(previous) = self.shared_buffer.take() {
+            self.retired_buffers.push(previous);
+        }
+
+        self.audio_buffer = Some(buffer);
+        self.live_buffer = live_buffer;
+        self.rebuild_mip_map();
+    }
+
+    /**
+    Sets a storage buffer for a mip map of the audio buffer. Whenever an audio buffer is set, it
+    is being filtered into up to `MAX_MIP_LEVELS` octaves, each half as long as the previous one.
+    Grains with a pitch above 1 read from the octave matching their pitch, so they don't alias.
+
+    The octaves are being filtered a few frames per rendered sample, once no grain is reading
+    from the previous ones anymore. Until they are done, grains read from the audio buffer only.
+
+    A storage buffer as long as the audio buffer fits all octaves, shorter ones fit less. Live
+    buffers are never being filtered, since they change all the time.
+
+    ## Example
+
+    ```
+    let buffer = [0_f32; 48_000];
+    let mut storage = [0_f32; 48_000];
+
+    let mut g = granulator::Granulator::new(48_000);
+    g.set_mip_map_storage(&mut storage);
+    g.set_audio_buffer(&buffer);
+    ```
+    */
+    pub fn set_mip_map_storage(&mut self, storage: &'a mut [T]) {
+        self.mip_map_storage = Some(BufferSlice::from_mut_slice(storage));
+        self.rebuild_mip_map();
+    }
+
+    /// Stops filtering audio buffers into octaves, grains read from the audio buffer only.
+    pub fn disable_mip_map(&mut self) {
+        self.mip_map_storage = None;
+        self.mip_map_builder = None;
+        self.mip_map = MipMap::new();
+    }
+
+    /// Prepares filtering the audio buffer into a new mip map, which is being done while
+    /// rendering. The previous mip map stays in place until then.
+    fn rebuild_mip_map(&mut self) {
+        self.mip_map_builder = match (&self.audio_buffer, &self.mip_map_storage, &self.live_buffer)
+        {
+            (Some(buffer), Some(storage), None) => Some(MipMapBuilder::new(buffer, storage)),
+            _ => None,
+        };
+        if self.mip_map_builder.is_none() {
+            self.mip_map = MipMap::new();
+        }
+    }
+
+    /// Filters the next frames of a pending mip map. The storage is only being overwritten once
+    /// no grain reads from the previous mip map anymore.
+    fn build_mip_map(&mut self, frames: usize) {
+        let Some(builder) = &mut self.mip_map_builder else {
+            return;
+        };
+
+        let in_use = self
+            .grains
+            .get_grains()
+            .iter()
+            .any(|grain| !grain.finished && self.mip_map.contains(grain));
+        if !in_use && builder.build(frames * MIP_MAP_FRAMES_PER_SAMPLE) {
+            self.mip_map = self.mip_map_builder.take().unwrap().finish();
+        }
+    }
+
+    /**
+    Returns whether no grain is reading from any previously set audio buffer anymore, so it can
+    be freed or reused.
+
+    ## Example
+
+    ```
+    use core::time::Duration;
+    use granulator::BufferSwap;
+
+    let first_buffer = [0_f32; 48_000];
+    let second_buffer = [0_f32; 48_000];
+
+    let mut g = granulator::Granulator::new(48_000);
+    g.set_buffer_swap(BufferSwap::FadeOut(Duration::from_millis(5)));
+    g.set_audio_buffer(&first_buffer);
+    g.set_active_grains(10);
+    g.process_block(&mut [0.0; 64]);
+
+    g.set_audio_buffer(&second_buffer);
+    g.process_block(&mut [0.0; 480]);
+    assert!(g.is_previous_buffer_released());
+    ```
+    */
+    pub fn is_previous_buffer_released(&self) -> bool {
+        match &self.audio_buffer {
+            Some(buffer) => self.grains.get_grains().iter().all(|grain| {
+                // a pending mip map belongs to the current buffer, the one in place doesn't
+                grain.finished
+                    || grain.reads_from(buffer)
+                    || self.mip_map_builder.is_none() && self.mip_map.contains(grain)
+            }),
+            None => self.grains.get_grains().iter().all(|grain| grain.finished),
+        }
+    }
+
+    /// Freezes the live buffer, so incoming samples are not being written anymore and grains keep
+    /// on playing the captured material. Releasing the freeze crossfades from the captured
+    /// material back to live input at the write head. Does nothing if no live buffer is set.
+    pub fn set_freeze(&mut self, freeze: bool) {
+        let crossfade_length = self.fs * FREEZE_CROSSFADE_MS / 1000;
+        if let Some(live_buffer) = &mut self.live_buffer {
+            if freeze {
+                live_buffer.freeze();
+            } else {
+                live_buffer.unfreeze(crossfade_length);
+            }
+        }
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.live_buffer
+            .as_ref()
+            .is_some_and(|live_buffer| live_buffer.is_frozen())
+    }
+
+    /// Writes the incoming samples into the live buffer. Does nothing if no live buffer is set.
+    ///
+    /// Call this in the audio callback before rendering the block.
+    pub fn push_live_input(&mut self, input: &[T]) {
+        if let Some(live_buffer) = &mut self.live_buffer {
+            live_buffer.push(input);
+        }
+    }
+
+    // ====================
+    // SCHEDULER MANAGEMENT
+    // ====================
+
+    /// Updates the internal scheduler which keeps track of which grain will be started/triggered
+    /// at which point in time. It also removes every grain, that has finished playing.
+    ///
+    /// The scheduler counts the samples rendered in the audio callback, which already starts every
+    /// grain on the exact sample it was scheduled for. This is only kept for compatibility, the
+    /// `time_step` is ignored.
+    pub fn update_scheduler(&mut self, _time_step: Duration) {
+        if self.audio_buffer.is_some() {
+            self.update_grains();
+        }
+    }
+
+    /// Removes finished grains, schedules new ones and starts every grain that is due.
+    fn update_grains(&mut self) {
+        self.remove_finished_grains();
+        self.spawn_future_grains();
+
+        // called for every sample by `get_next_sample`, so only do more if a grain is due
+        if self.scheduler.samples_until_next_grain() == Some(0) {
+            let due = self.scheduler.take_due_grains();
+            self.activate_grains(&due);
+
+            // the next grain of a fixed density is counted from the start of the previous one
+            self.spawn_future_grains();
+        }
+    }
+
+    fn samples_until_next_event(&self) -> usize {
+        let next_start = self
+            .scheduler
+            .samples_until_next_grain()
+            .map_or(usize::MAX, |samples| {
+                usize::try_from(samples).unwrap_or(usize::MAX)
+            });
+        let next_finish = self
+            .grains
+            .samples_until_next_finish()
+            .unwrap_or(usize::MAX);
+
+        next_start.min(next_finish)
+    }
+
+    fn spawn_future_grains(&mut self) {
+        if !self.settings.auto_emission {
+            return;
+        }
+
+        match self.settings.emission_mode {
+            EmissionMode::Refill => self.refill_grains(),
+            EmissionMode::Tempo => {
+                // an external clock starts its grains on its own ticks
+                if self.scheduler.pending_emissions() == 0
+                    && !self.scheduler.tempo_clock.is_external()
+                {
+                    let interval = self.scheduler.tempo_clock.next_interval(self.fs);
+                    self.schedule_tempo_step(interval);
+                }
+            }
+            _ => {
+                // triggered grains don't hold up the next one of the density
+                if self.scheduler.pending_emissions() == 0 {
+                    let id = self.get_new_id();
+                    let interval = self.get_new_emission_interval();
+                    self.scheduler.schedule_emission(id, interval).ok();
+                }
+            }
+        }
+    }
+
+    /// Schedules a grain on the next step of the tempo clock, following the pattern if there is
+    /// one.
+    fn schedule_tempo_step(&mut self, delay: u64) {
+        let id = self.get_new_id();
+        self.scheduler.schedule_step(id, delay, &mut self.rng).ok();
+    }
+
+    fn refill_grains(&mut self) {
+        // the difference between active grains parameter and already playing or scheduled grains,
+        // but never less than zero. Scheduled triggers are only counted once they play.
+        let to_be_spawned = self
+            .settings
+            .active_grains
+            .saturating_sub(self.grains.get_grains().len() + self.scheduler.pending_emissions());
+
+        // spawn future grains
+        for _ in 0..to_be_spawned {
+            let id = self.get_new_id();
+            let delay = self.get_new_delay();
+            self.scheduler
+                .schedule_emission(id, self.duration_in_samples(delay))
+                .ok();
+        }
+    }
+
+    fn activate_grains(&mut self, due: &Vec<TimeInfo, MAX_GRAINS>) {
+        if self.audio_buffer.is_some() {
+            for time_info in due {
+                // grains of a fixed density above the voice limit are skipped, just like
+                // triggered grains once all voices are playing and silent steps of a pattern
+                if time_info.accent <= 0.0
+                    || self.settings.emission_mode != EmissionMode::Refill
+                        && self.grains.get_grains().len() >= self.settings.active_grains
+                    || self.grains.get_grains().is_full()
+                {
+                    continue;
+                }
+
+                match time_info.spec {
+                    Some(spec) => self.start_grain_from_spec(time_info.id, &spec),
+                    None => self.start_spread_grain(time_info.id, time_info.accent),
+                }
+            }
+        }
+    }
+
+    /// Starts a grain with randomly spread parameters, with its velocity scaled by the accent.
+    fn start_spread_grain(&mut self, id: usize, accent: f32) {
+        let velocity = self.get_new_velocity() * accent;
+        let pan = self.get_new_pan();
+        let pitch = self.get_new_pitch();
+        let offset = self.get_new_offset();
+        let grain_size = self.get_new_grain_size() as usize;
+
+        let parameters = GrainParameters {
+            window: self.get_new_window(),
+            window_param: self.settings.window_param,
+            window_skew: self.get_new_window_skew(),
+            morph: self
+                .settings
+                .morph_target
+                .map(|target| (target, self.settings.window_morph)),
+            pitch,
+            velocity,
+            pan,
+            channel: self.get_new_channel(),
+            interpolation: self.settings.interpolation,
+            length: None,
+            reverse: self.get_new_direction(),
+            ping_pong: self.settings.ping_pong,
+        };
+        self.start_grain(id, offset, grain_size, parameters);
+    }
+
+    /// Starts a grain with the given parameters. Everything else is left at its default, except
+    /// for the channels and the interpolation, unless the grain has its own.
+    fn start_grain_from_spec(&mut self, id: usize, spec: &GrainSpec) {
+        let parameters = GrainParameters {
+            window: spec.window,
+            window_param: spec.window_param,
+            pitch: spec.pitch,
+            velocity: spec.velocity.clamp(0.0, 1.0),
+            pan: spec.pan.clamp(0.0, 1.0),
+            channel: self.get_new_channel(),
+            interpolation: spec.interpolation.unwrap_or(self.settings.interpolation),
+            ..Default::default()
+        };
+        self.start_grain(id, spec.offset, spec.length, parameters);
+    }
+
+    /// Starts a grain reading `length` samples from `offset` on. Pitched up grains read from the
+    /// octave of the mip map matching their pitch.
+    fn start_grain(
+        &mut self,
+        id: usize,
+        mut offset: usize,
+        mut length: usize,
+        mut parameters: GrainParameters,
+    ) {
+        let mut source = self.get_source_slice(&mut offset, &mut length);
+
+        let octave = match self.mip_map_builder {
+            Some(_) => None, // the mip map still belongs to the previous buffer
+            None => self.mip_map.get_level(parameters.pitch),
+        };
+        if let Some((level, octave)) = octave {
+            parameters.pitch /= (1 << level) as f32;
+            source = octave.get_sub_slice(&mut (offset >> level), &mut (length >> level).max(1));
+        }
+
+        parameters.length = Some(length);
+        self.grains.push_grain(id, source, parameters).unwrap();
+    }
+
+    fn get_source_slice(&self, offset: &mut usize, length: &mut usize) -> BufferSlice<T> {
+        match &self.live_buffer {
+            Some(live_buffer) => live_buffer.get_sub_slice_behind_write_head(offset, length),
+            None => self
+                .audio_buffer
+                .as_ref()
+                .unwrap()
+                .get_sub_slice(offset, length),
+        }
+    }
+
+    fn remove_finished_grains(&mut self) {
+        let grains_vector = self.grains.get_mut_grains();
+
+        for i in (0..grains_vector.len()).rev() {
+            if grains_vector[i].finished {
+                grains_vector.swap_remove(i);
+            }
+        }
+    }
+
+    fn duration_in_samples(&self, duration: Duration) -> u64 {
+        (duration.as_secs_f32() * self.fs as f32) as u64
+    }
+
+    fn get_new_id(&mut self) -> usize {
         let current_id = self.current_id_counter;
         if self.current_id_counter >= usize::MAX - 1 {
             self.current_id_counter = 0;
@@ -415,7 +1688,12 @@ impl Granulator {
             self.get_spreaded(Offset);
             let mut random_offset = self.random_offset_value;
 
-            let max_length = self.audio_buffer.as_ref().unwrap().length as usize - 1000;
+            let max_length = self
+                .audio_buffer
+                .as_ref()
+                .unwrap()
+                .length
+                .saturating_sub(1000);
 
             if random_offset >= max_length {
                 random_offset = max_length;
@@ -437,6 +1715,18 @@ impl Granulator {
         }
     }
 
+    fn update_window_table(&mut self) {
+        if self.settings.exact_windows {
+            self.grains.update_window_tables(None, None, 0.0);
+        } else {
+            self.grains.update_window_tables(
+                Some(self.settings.window_function),
+                self.settings.morph_target,
+                self.settings.window_param,
+            );
+        }
+    }
+
     fn get_new_window(&self) -> WindowFunction {
         self.settings.window_function
     }
@@ -444,14 +1734,7 @@ impl Granulator {
     fn get_new_pitch(&mut self) -> f32 {
         if self.settings.sp_pitch >= SPREAD_ESPILON {
             self.get_spreaded(Pitch);
-            let mut random_pitch = self.random_pitch_value;
-
-            if random_pitch <= 0.1 {
-                random_pitch = 0.1;
-            }
-            if random_pitch >= 10.0 {
-                random_pitch = 10.0;
-            }
+            let random_pitch = self.random_pitch_value.clamp(0.1, 10.0);
 
             random_pitch.autotune_to(Some(self.pitch_ratios))
         } else {
@@ -472,18 +1755,65 @@ impl Granulator {
     fn get_new_velocity(&mut self) -> f32 {
         if self.settings.sp_velocity >= SPREAD_ESPILON {
             self.get_spreaded(Velocity);
-            let mut random_velocity = self.random_velocity_value;
+            self.random_velocity_value.clamp(0.0, 1.0)
+        } else {
+            self.settings.velocity
+        }
+    }
 
-            if random_velocity < 0.0 {
-                random_velocity = 0.0;
-            }
-            if random_velocity > 1.0 {
-                random_velocity = 1.0;
+    fn get_new_channel(&mut self) -> Option<usize> {
+        match self.settings.channel_mode {
+            ChannelMode::All => None,
+            ChannelMode::Fixed(channel) => Some(channel),
+            ChannelMode::Random => {
+                let channels = self.audio_buffer.as_ref().unwrap().channels as u32;
+                Some(self.rng.rand_range(0..channels) as usize)
             }
+        }
+    }
+
+    /// Returns whether the next grain plays backwards.
+    fn get_new_direction(&mut self) -> bool {
+        self.rng.rand_float() < self.settings.reverse_probability
+    }
+
+    /// Returns the amount of samples until the next grain starts in the emission modes driven by
+    /// the density.
+    fn get_new_emission_interval(&mut self) -> u64 {
+        let density = if self.settings.sp_density >= SPREAD_ESPILON {
+            self.get_spreaded(Density);
 
-            random_velocity
+            self.random_density_value
         } else {
-            self.settings.velocity
+            self.settings.density
+        };
+
+        get_onset_interval(
+            self.settings.emission_mode,
+            self.fs as f32 / density,
+            self.settings.jitter,
+            &mut self.scheduler.remainder,
+            &mut self.rng,
+        )
+    }
+
+    fn get_new_window_skew(&mut self) -> f32 {
+        if self.settings.sp_window_skew >= SPREAD_ESPILON {
+            self.get_spreaded(WindowSkew);
+
+            self.random_window_skew_value.clamp(0.0, 1.0)
+        } else {
+            self.settings.window_skew
+        }
+    }
+
+    fn get_new_pan(&mut self) -> f32 {
+        if self.settings.sp_pan >= SPREAD_ESPILON {
+            self.get_spreaded(Pan);
+
+            self.random_pan_value.clamp(0.0, 1.0)
+        } else {
+            self.settings.pan
         }
     }
 
@@ -521,6 +1851,20 @@ impl Granulator {
                 self.random_velocity_value = self.settings.velocity
                     + self.settings.sp_velocity * get_random_bipolar_float(&mut self.rng);
             }
+            Pan => {
+                self.random_pan_value = self.settings.pan
+                    + self.settings.sp_pan * get_random_bipolar_float(&mut self.rng);
+            }
+            Density => {
+                // spread by up to an octave in both directions
+                self.random_density_value = self.settings.density
+                    * 2.0_f32
+                        .powf(self.settings.sp_density * get_random_bipolar_float(&mut self.rng));
+            }
+            WindowSkew => {
+                self.random_window_skew_value = self.settings.window_skew
+                    + self.settings.sp_window_skew * get_random_bipolar_float(&mut self.rng);
+            }
             _ => {}
         }
     }
@@ -552,10 +1896,10 @@ mod tests {
 
         let mut ids = Vec::new();
 
-        assert!(m.grains.get_grains().len() == 0);
+        assert!(m.grains.get_grains().is_empty());
 
         for _ in 0..MAX_GRAINS {
-            ids.push(m.get_new_id()).unwrap();
+            ids.push(TimeInfo::new(m.get_new_id(), 0, None)).unwrap();
         }
 
         m.activate_grains(&ids);
@@ -582,10 +1926,8 @@ mod tests {
         let buffer = [1_f32; 10000];
 
         let mut check_slice: Vec<usize, MAX_GRAINS> = Vec::new();
-        let mut zero_slice: Vec<usize, MAX_GRAINS> = Vec::new();
         for i in 0..MAX_GRAINS {
             check_slice.push(i).unwrap();
-            zero_slice.push(0).unwrap();
         }
 
         m.set_audio_buffer(&buffer);
@@ -594,15 +1936,16 @@ mod tests {
 
         // update scheduler
         m.spawn_future_grains();
-        let ids = m.scheduler.update_clock(Duration::from_millis(20));
+        m.scheduler.advance_clock(960);
+        let due = m.scheduler.take_due_grains();
 
-        assert!(ids == check_slice);
+        assert!(due.iter().map(|time_info| time_info.id).eq(check_slice));
 
-        m.activate_grains(&ids);
+        m.activate_grains(&due);
         m.remove_finished_grains();
 
         assert!(m.grains.get_grains().len() == MAX_GRAINS);
-        assert!(m.scheduler.future_vector.len() == MAX_GRAINS);
+        assert!(m.scheduler.future_vector.is_empty());
 
         // finish all grains
         for _ in 0..481 {
@@ -611,24 +1954,1047 @@ mod tests {
 
         // update schedular
         m.spawn_future_grains();
-        let ids = m.scheduler.update_clock(Duration::from_millis(20));
-        assert!(ids == Vec::<usize, MAX_GRAINS>::new());
-        m.activate_grains(&ids);
+        m.scheduler.advance_clock(960);
+        let due = m.scheduler.take_due_grains();
+        assert!(due.is_empty());
+        m.activate_grains(&due);
         m.remove_finished_grains();
 
-        assert!(m.grains.get_grains().len() == 0);
-        assert!(m.scheduler.future_vector.len() == 0);
+        assert!(m.grains.get_grains().is_empty());
+        assert!(m.scheduler.future_vector.is_empty());
 
         // next cycle
 
         m.spawn_future_grains();
-        assert!(m.grains.get_grains().len() == 0);
+        assert!(m.grains.get_grains().is_empty());
         assert!(m.scheduler.future_vector.len() == MAX_GRAINS);
 
-        let ids = m.scheduler.update_clock(Duration::from_millis(20));
-        m.activate_grains(&ids);
+        m.scheduler.advance_clock(960);
+        let due = m.scheduler.take_due_grains();
+        m.activate_grains(&due);
         assert!(m.grains.get_grains().len() == MAX_GRAINS);
-        assert!(m.scheduler.future_vector.len() == MAX_GRAINS);
+        assert!(m.scheduler.future_vector.is_empty());
+    }
+
+    #[test]
+    fn pan_grains_hard_left() {
+        let mut m = Granulator::new(FS);
+        let buffer = [1_f32; 10000];
+        m.set_audio_buffer(&buffer);
+        m.set_active_grains(1);
+        m.set_grain_size(10.0);
+        m.set_parameter(Pan, 0.0);
+
+        m.update_scheduler(Duration::from_millis(1));
+
+        let mut left_sum = 0.0;
+        for _ in 0..480 {
+            let (left, right) = m.get_next_frame();
+            assert!(right.abs() < 0.001);
+            left_sum += left;
+        }
+        assert!(left_sum > 0.0);
+    }
+
+    #[test]
+    fn spread_pan_stays_in_bounds() {
+        let mut m = Granulator::new(FS);
+        let buffer = [0_f32; 100];
+        m.set_audio_buffer(&buffer);
+        m.set_parameter(Pan, 0.9);
+        m.set_parameter(PanSpread, 1.0);
+
+        for _ in 0..1000 {
+            let pan = m.get_new_pan();
+            assert!((0.0..=1.0).contains(&pan));
+        }
+    }
+
+    #[test]
+    fn start_grain_on_exact_sample() {
+        let mut m = Granulator::new(FS);
+        let buffer = [1_f32; 10000];
+        m.set_audio_buffer(&buffer);
+        m.set_active_grains(1);
+        m.set_grain_size(10.0);
+        m.settings.delay = Duration::from_millis(1); // 48 samples
+
+        m.update_scheduler(Duration::from_millis(20));
+        assert!(m.grains.get_grains().is_empty());
+
+        let mut block = [0_f32; 64];
+        m.process_block(&mut block[..48]);
+        assert!(m.grains.get_grains().is_empty());
+
+        m.process_block(&mut block[48..]);
+        assert!(m.grains.get_grains().len() == 1);
+
+        // the envelope of the grain starts at zero
+        assert!(block[..49].iter().all(|sample| *sample == 0.0));
+        assert!(block[49] > 0.0);
+    }
+
+    #[test]
+    fn refill_grains_on_exact_sample() {
+        let mut m = Granulator::new(FS);
+        let buffer = [1_f32; 10000];
+        m.set_audio_buffer(&buffer);
+        m.set_active_grains(1);
+        m.set_grain_size(1.0); // 48 samples
+
+        let mut block = [0_f32; 128];
+        m.process_block(&mut block);
+
+        // the first grain outputs 48 samples plus a silent one, after which the next one starts
+        assert!(block[0] == 0.0 && block[1] > 0.0);
+        assert!(block[48] == 0.0 && block[49] == 0.0 && block[50] > 0.0);
+    }
+
+    #[test]
+    fn granulate_live_input() {
+        let mut m = Granulator::new(FS);
+        let mut ring_buffer = [0_f32; 4800];
+        m.set_live_buffer(&mut ring_buffer);
+        m.set_active_grains(4);
+        m.set_grain_size(1.0);
+        m.set_parameter(Offset, 0.1); // 480 samples delay
+
+        assert!(m.settings.offset == 480);
+
+        let input = [1_f32; 64];
+        let mut output = [0_f32; 64];
+        let mut output_sum = 0.0;
+        for i in 0..100 {
+            m.push_live_input(&input);
+            m.process_block(&mut output);
+
+            // nothing has been written that far behind the write head yet
+            if i < 6 {
+                assert!(output.iter().all(|sample| *sample == 0.0));
+            }
+            output_sum += output.iter().sum::<f32>();
+        }
+        assert!(output_sum > 0.0);
+    }
+
+    #[test]
+    fn freeze_live_input() {
+        let mut m = Granulator::new(FS);
+        let mut ring_buffer = [0_f32; 4800];
+        m.set_live_buffer(&mut ring_buffer);
+        m.set_active_grains(4);
+        m.set_grain_size(1.0);
+        m.set_parameter(OffsetSpread, 1.0);
+
+        for _ in 0..75 {
+            m.push_live_input(&[1.0; 64]);
+        }
+        m.set_freeze(true);
+        assert!(m.is_frozen());
+
+        // silence is not being captured, even after more than the whole buffer has been pushed
+        let mut output = [0_f32; 64];
+        let mut output_sum = 0.0;
+        for i in 0..100 {
+            m.push_live_input(&[0.0; 64]);
+            m.process_block(&mut output);
+            if i >= 80 {
+                output_sum += output.iter().sum::<f32>();
+            }
+        }
+        assert!(output_sum > 0.0);
+
+        m.set_freeze(false);
+        assert!(!m.is_frozen());
+    }
+
+    fn setup_spreaded_granulator<T: Sample>(buffer: &[T]) -> Granulator<'_, T> {
+        let mut m = Granulator::with_sample_type(FS);
+        m.rng = Rand32::new(1);
+        m.set_audio_buffer(buffer);
+        m.set_active_grains(MAX_GRAINS);
+        m.set_grain_size(10.0);
+        m.set_parameter(OffsetSpread, 0.5);
+        m.set_parameter(PitchSpread, 0.3);
+        m.set_parameter(VelocitySpread, 0.5);
+        m.set_parameter(PanSpread, 1.0);
+        m
+    }
+
+    #[test]
+    fn block_equals_per_sample() {
+        let mut buffer = [0_f32; 10000];
+        for (i, sample) in buffer.iter_mut().enumerate() {
+            *sample = ((i % 100) as f32 / 50.0) - 1.0;
+        }
+        let mut per_sample = setup_spreaded_granulator(&buffer);
+        let mut per_block = setup_spreaded_granulator(&buffer);
+
+        for _ in 0..20 {
+            per_sample.update_scheduler(Duration::from_millis(1));
+            per_block.update_scheduler(Duration::from_millis(1));
+
+            let mut block = [0_f32; 64];
+            per_block.process_block(&mut block);
+            for sample in block {
+                assert!(sample == per_sample.get_next_sample());
+            }
+        }
+    }
+
+    #[test]
+    fn render_the_window_into_a_table() {
+        let buffer = [0_f32; 1000];
+        let mut m = Granulator::new(FS);
+        m.set_audio_buffer(&buffer);
+
+        let holds = |m: &Granulator, window, param| {
+            m.grains
+                .get_window_table()
+                .is_some_and(|table| table.matches(window, param))
+        };
+        assert!(holds(&m, WindowFunction::Sine, 0.0));
+
+        m.set_window_function(2);
+        assert!(holds(&m, WindowFunction::Hamming, 0.0));
+        m.set_parameter(WindowParam, 0.5);
+        assert!(holds(&m, WindowFunction::Hamming, 0.5));
+
+        m.set_exact_windows(true);
+        assert!(m.grains.get_window_table().is_none());
+        m.set_window_function(4);
+        assert!(m.grains.get_window_table().is_none());
+
+        m.set_exact_windows(false);
+        assert!(holds(&m, WindowFunction::Tukey, 0.5));
+    }
+
+    #[test]
+    fn play_a_custom_window() {
+        let buffer = [1_f32; 1000];
+        let mut m = Granulator::new(FS);
+        m.set_audio_buffer(&buffer);
+
+        assert!(m.set_custom_window(CustomWindow::Table(&[])) == Err(0));
+        assert!(m
+            .set_custom_window(CustomWindow::Table(&[0.5, 0.5]))
+            .is_ok());
+        m.set_window_function(14);
+
+        m.update_scheduler(Duration::ZERO);
+        let first = m.get_next_sample();
+        assert!(first > 0.0);
+        for _ in 0..400 {
+            assert!(m.get_next_sample() == first);
+        }
+    }
+
+    #[test]
+    fn capture_the_window_morph_at_start() {
+        let buffer = [1_f32; 1000];
+        let [mut plain, mut later, mut morphed] = [0.0, 0.0, 1.0].map(|morph| {
+            let mut m = Granulator::new(FS);
+            m.set_audio_buffer(&buffer);
+            m.set_window_function(1);
+            m.set_window_morph(Some(WindowFunction::Rectangular));
+            m.set_parameter(WindowMorph, morph);
+            m.update_scheduler(Duration::ZERO);
+            m
+        });
+
+        // playing grains keep the amount they started with
+        later.set_parameter(WindowMorph, 1.0);
+
+        let (mut plain_sum, mut morphed_sum) = (0.0, 0.0);
+        for _ in 0..100 {
+            let sample = plain.get_next_sample();
+            assert!(later.get_next_sample() == sample);
+
+            plain_sum += sample;
+            morphed_sum += morphed.get_next_sample();
+        }
+        assert!(morphed_sum > 2.0 * plain_sum);
+    }
+
+    #[test]
+    fn window_table_sounds_like_exact_windows() {
+        let mut buffer = [0_f32; 10000];
+        for (i, sample) in buffer.iter_mut().enumerate() {
+            *sample = ((i % 100) as f32 / 50.0) - 1.0;
+        }
+        let mut exact = setup_spreaded_granulator(&buffer);
+        let mut table = setup_spreaded_granulator(&buffer);
+        exact.set_exact_windows(true);
+        for m in [&mut exact, &mut table] {
+            m.set_window_function(3);
+            m.set_parameter(WindowParam, 0.3);
+        }
+
+        for _ in 0..20 {
+            exact.update_scheduler(Duration::from_millis(1));
+            table.update_scheduler(Duration::from_millis(1));
+
+            let mut exact_block = [0_f32; 64];
+            let mut table_block = [0_f32; 64];
+            exact.process_block(&mut exact_block);
+            table.process_block(&mut table_block);
+            for (exact, table) in exact_block.into_iter().zip(table_block) {
+                assert!((exact - table).abs() < 0.0001);
+            }
+        }
+    }
+
+    #[test]
+    fn stereo_block_equals_per_frame() {
+        let mut buffer = [0_f32; 10000];
+        for (i, sample) in buffer.iter_mut().enumerate() {
+            *sample = ((i % 100) as f32 / 50.0) - 1.0;
+        }
+        let mut per_frame = setup_spreaded_granulator(&buffer);
+        let mut per_block = setup_spreaded_granulator(&buffer);
+
+        for _ in 0..20 {
+            per_frame.update_scheduler(Duration::from_millis(1));
+            per_block.update_scheduler(Duration::from_millis(1));
+
+            let mut left = [0_f32; 64];
+            let mut right = [0_f32; 64];
+            per_block.process_stereo_block(&mut left, &mut right);
+            for frame in left.into_iter().zip(right) {
+                assert!(frame == per_frame.get_next_frame());
+            }
+        }
+    }
+
+    #[test]
+    fn play_integer_samples() {
+        let mut float_buffer = [0_f32; 10000];
+        let mut integer_buffer = [0_i16; 10000];
+        for i in 0..10000 {
+            float_buffer[i] = ((i % 8) as f32 - 4.0) / 8.0;
+            integer_buffer[i] = ((i % 8) as i16 - 4) * 4096;
+        }
+        let mut float = setup_spreaded_granulator(&float_buffer);
+        let mut integer = setup_spreaded_granulator(&integer_buffer);
+
+        let mut float_block = [0_f32; 64];
+        let mut integer_block = [0_f32; 64];
+        for _ in 0..20 {
+            float.process_block(&mut float_block);
+            integer.process_block(&mut integer_block);
+            assert!(float_block == integer_block);
+        }
+    }
+
+    #[test]
+    fn set_a_multi_channel_buffer() {
+        let mut m = Granulator::new(FS);
+        let buffer = [0_f32; 1000];
+
+        assert!(m.set_interleaved_audio_buffer(&buffer, 0).is_err());
+        assert!(m
+            .set_interleaved_audio_buffer(&buffer, MAX_CHANNELS + 1)
+            .is_err());
+        assert!(m.set_interleaved_audio_buffer(&buffer, 4).is_ok());
+        assert!(m.audio_buffer.as_ref().unwrap().length == 250);
+
+        m.set_parameter(Offset, 1.0);
+        assert!(m.settings.offset == 250);
+    }
+
+    #[test]
+    fn finish_grains_on_previous_buffer() {
+        let first_buffer = [1_f32; 10_000];
+        let second_buffer = [1_f32; 10_000];
+
+        let mut m = Granulator::new(FS);
+        m.set_audio_buffer(&first_buffer);
+        m.set_active_grains(10);
+        m.process_block(&mut [0.0; 64]);
+
+        m.set_audio_buffer(&second_buffer);
+        assert!(!m.is_previous_buffer_released());
+
+        // the grains finish on the sample after their last one
+        m.process_block(&mut [0.0; 416]);
+        assert!(!m.is_previous_buffer_released());
+
+        m.process_block(&mut [0.0; 1]);
+        assert!(m.is_previous_buffer_released());
+    }
+
+    #[test]
+    fn fade_out_grains_on_previous_buffer() {
+        let first_buffer = [1_f32; 10_000];
+        let second_buffer = [1_f32; 10_000];
+
+        let mut m = Granulator::new(FS);
+        m.set_buffer_swap(BufferSwap::FadeOut(Duration::from_millis(1)));
+        m.set_audio_buffer(&first_buffer);
+        m.set_active_grains(10);
+        m.process_block(&mut [0.0; 64]);
+
+        m.set_audio_buffer(&second_buffer);
+        m.process_block(&mut [0.0; 47]);
+        assert!(!m.is_previous_buffer_released());
+
+        m.process_block(&mut [0.0; 1]);
+        assert!(m.is_previous_buffer_released());
+
+        // new grains are reading from the new buffer right away
+        m.process_block(&mut [0.0; 1]);
+        assert!(m.grains.get_grains().len() == 10);
+    }
+
+    #[test]
+    fn reverse_some_grains() {
+        let buffer = [0_f32; 1000];
+        let mut m = Granulator::new(FS);
+        m.rng = Rand32::new(1);
+        m.set_audio_buffer(&buffer);
+
+        assert!((0..100).all(|_| !m.get_new_direction()));
+
+        m.set_parameter(ReverseProbability, 0.25);
+        let reversed = (0..1000).filter(|_| m.get_new_direction()).count();
+        assert!((200..300).contains(&reversed));
+
+        m.set_parameter(ReverseProbability, 1.0);
+        assert!((0..100).all(|_| m.get_new_direction()));
+    }
+
+    #[test]
+    fn spread_the_window_skew() {
+        let buffer = [0_f32; 1000];
+        let mut m = Granulator::new(FS);
+        m.rng = Rand32::new(1);
+        m.set_audio_buffer(&buffer);
+
+        m.set_parameter(WindowSkew, 0.2);
+        assert!((0..100).all(|_| m.get_new_window_skew() == 0.2));
+
+        m.set_parameter(WindowSkewSpread, 0.5);
+        let skews: [f32; 100] = core::array::from_fn(|_| m.get_new_window_skew());
+        assert!(skews.iter().all(|skew| (0.0..=0.7).contains(skew)));
+        assert!(skews.iter().any(|skew| *skew < 0.1));
+        assert!(skews.iter().any(|skew| *skew > 0.5));
+    }
+
+    #[test]
+    fn emit_grains_at_a_fixed_density() {
+        let buffer = [0_f32; 48_000];
+        let mut m = Granulator::new(FS);
+        m.set_audio_buffer(&buffer);
+        m.set_emission_mode(EmissionMode::Synchronous);
+        m.set_density(100.0);
+        m.set_active_grains(MAX_GRAINS);
+
+        // short grains don't overlap, long ones do, but both start at the same rate
+        for (grain_size, playing) in [(5.0, 0), (50.0, 5)] {
+            m.set_grain_size(grain_size);
+            m.grains.flush();
+            m.scheduler.future_vector.clear();
+            let started = m.current_id_counter;
+
+            let mut block = [0_f32; 480];
+            for _ in 0..100 {
+                m.process_block(&mut block);
+            }
+            assert!(m.current_id_counter - started == 100);
+
+            let grains = m.grains.get_grains();
+            assert!(grains.iter().filter(|grain| !grain.finished).count() == playing);
+        }
+    }
+
+    #[test]
+    fn move_the_next_grain_to_a_new_density() {
+        let buffer = [0_f32; 48_000];
+        let mut m = Granulator::new(FS);
+        m.set_audio_buffer(&buffer);
+        m.set_emission_mode(EmissionMode::Synchronous);
+        m.set_density(1.0);
+
+        m.process_block(&mut [0_f32; 480]);
+        assert!(m.scheduler.samples_until_next_grain() == Some(47_520));
+
+        m.set_density(100.0);
+        assert!(m.scheduler.samples_until_next_grain() == Some(475));
+        m.set_density(100.0);
+        assert!(m.scheduler.samples_until_next_grain() == Some(475));
+    }
+
+    #[test]
+    fn start_with_the_same_density_in_user_settings() {
+        let buffer = [0_f32; 1000];
+        let mut m = Granulator::new(FS);
+        m.set_audio_buffer(&buffer);
+        let density = m.settings.density;
+
+        m.update_all_user_settings(&UserSettings::new_empty());
+        assert!((m.settings.density - density).abs() < 0.001);
+    }
+
+    #[test]
+    fn select_the_emission_mode_in_user_settings() {
+        let buffer = [0_f32; 1000];
+        let mut m = Granulator::new(FS);
+        m.set_audio_buffer(&buffer);
+        let mut settings = UserSettings::new_empty();
+
+        for (index, mode) in [
+            (3, EmissionMode::Asynchronous),
+            (2, EmissionMode::QuasiSynchronous),
+            (1, EmissionMode::Synchronous),
+            (0, EmissionMode::Refill),
+        ] {
+            settings.emission_mode = index;
+            m.update_all_user_settings(&settings);
+            assert!(m.settings.emission_mode == mode);
+        }
+    }
+
+    #[test]
+    fn start_grains_on_the_beat() {
+        let buffer = [0_f32; 48_000];
+        let mut m = Granulator::new(FS);
+        m.set_audio_buffer(&buffer);
+        m.set_active_grains(MAX_GRAINS);
+        m.set_tempo(120.0);
+        m.set_note_division(NoteDivision::Straight(4));
+        m.set_emission_mode(EmissionMode::Tempo);
+
+        let mut block = [0_f32; 64];
+        m.process_block(&mut block[..1]);
+        assert!(m.grains.get_grains().len() == 1);
+        assert!(m.scheduler.samples_until_next_grain() == Some(23_999));
+
+        for _ in 0..375 {
+            m.process_block(&mut block);
+        }
+        assert!(m.current_id_counter == 3);
+        assert!(m.scheduler.samples_until_next_grain() == Some(23_999));
+    }
+
+    #[test]
+    fn play_a_euclidean_rhythm() {
+        let buffer = [0_f32; 48_000];
+        let mut m = Granulator::new(FS);
+        m.set_audio_buffer(&buffer);
+        m.set_active_grains(MAX_GRAINS);
+        m.set_emission_mode(EmissionMode::Tempo);
+        m.set_euclidean_pattern(3, 8, 0).unwrap();
+
+        // the first step starts a grain right away, the next one is due a sixteenth note later
+        let mut block = [0_f32; 6000];
+        m.process_block(&mut block[..1]);
+        assert!(m.grains.get_grains().len() == 1);
+
+        let mut accents = [0.0; 16];
+        for accent in accents.iter_mut() {
+            *accent = m.scheduler.future_vector[0].accent;
+            m.process_block(&mut block);
+        }
+        assert!(accents[..8] == [0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 1.0]);
+        assert!(accents[..8] == accents[8..]);
+
+        // silent steps keep the time
+        assert!(m.scheduler.samples_until_next_grain() == Some(5999));
+
+        assert!(m.set_euclidean_pattern(3, 0, 0) == Err(0));
+        m.clear_pattern();
+        m.process_block(&mut block);
+        assert!(m.scheduler.future_vector[0].accent == 1.0);
+    }
+
+    #[test]
+    fn play_steps_by_chance() {
+        let buffer = [0_f32; 48_000];
+        let mut m = Granulator::new(FS);
+        m.rng = Rand32::new(1);
+        m.set_audio_buffer(&buffer);
+        m.set_emission_mode(EmissionMode::Tempo);
+        m.set_step_pattern(&[Step::new(0.25, 1.0), Step::new(1.0, 0.5)])
+            .unwrap();
+
+        let mut block = [0_f32; 6000];
+        let mut hits = [0; 2];
+        for step in 0..800 {
+            m.process_block(&mut block);
+
+            // the following step is already scheduled
+            let accent = m.scheduler.future_vector[0].accent;
+            match step % 2 {
+                0 => assert!(accent == 0.5),
+                _ => assert!(accent == 0.0 || accent == 1.0),
+            }
+            hits[(step + 1) % 2] += (accent > 0.0) as usize;
+        }
+
+        assert!((70..130).contains(&hits[0]));
+        assert!(hits[1] == 400);
+    }
+
+    #[test]
+    fn follow_an_external_clock() {
+        let buffer = [0_f32; 48_000];
+        let mut m = Granulator::new(FS);
+        m.set_audio_buffer(&buffer);
+        m.set_active_grains(MAX_GRAINS);
+        m.set_emission_mode(EmissionMode::Tempo);
+        m.set_external_clock(Some(24)).unwrap();
+
+        // sixteenth notes start on every 6th tick
+        let mut block = [0_f32; 64];
+        let mut started = [0; 24];
+        for started in started.iter_mut() {
+            m.clock_tick();
+            m.process_block(&mut block);
+            *started = m.current_id_counter;
+        }
+        assert!(started[0] == 1 && started[5] == 1);
+        assert!(started[6] == 2 && started[23] == 4);
+    }
+
+    #[test]
+    fn spread_the_steps_between_two_ticks() {
+        let buffer = [0_f32; 48_000];
+        let mut m = Granulator::new(FS);
+        m.set_audio_buffer(&buffer);
+        m.set_active_grains(MAX_GRAINS);
+        m.set_emission_mode(EmissionMode::Tempo);
+        m.set_note_division(NoteDivision::Straight(16));
+        m.set_external_clock(Some(1)).unwrap();
+        assert!(m.set_external_clock(Some(0)) == Err(0));
+
+        // the first tick doesn't know how far apart the ticks are yet
+        m.clock_tick();
+        m.process_block(&mut [0_f32; 64]);
+        assert!(m.current_id_counter == 4);
+
+        // the next ones are placed by the time since then
+        m.process_block(&mut [0_f32; 400]);
+        m.clock_tick();
+
+        let mut starts: heapless::Vec<u64, 4> = heapless::Vec::new();
+        let mut elapsed = 0;
+        while let Some(samples) = m.scheduler.samples_until_next_grain() {
+            elapsed += samples;
+            starts.push(elapsed).unwrap();
+
+            // up to and including the sample the grain starts on
+            let mut block = [0_f32; 464];
+            m.process_block(&mut block[..=samples as usize]);
+            elapsed += 1;
+        }
+        assert!(starts == [0, 116, 232, 348]);
+    }
+
+    #[test]
+    fn trigger_grains() {
+        let buffer = [1_f32; 1000];
+        let mut m = Granulator::new(FS);
+        m.set_audio_buffer(&buffer);
+        m.set_auto_emission(false);
+
+        let mut block = [0_f32; 64];
+        m.process_block(&mut block);
+        assert!(m.grains.get_grains().is_empty());
+
+        m.trigger().unwrap();
+        m.process_block(&mut block);
+        assert!(m.grains.get_grains().len() == 1);
+
+        m.grains.flush();
+        m.trigger_burst(5).unwrap();
+        m.process_block(&mut block);
+        assert!(m.grains.get_grains().len() == 5);
+
+        // more grains than fit into the scheduler
+        m.grains.flush();
+        assert!(m.trigger_burst(MAX_GRAINS + 3) == Err(3));
+        assert!(m.trigger() == Err(1));
+        m.process_block(&mut block);
+        assert!(m.grains.get_grains().len() == MAX_GRAINS);
+    }
+
+    #[test]
+    fn trigger_a_grain_on_an_exact_sample() {
+        let buffer = [1_f32; 1000];
+        let mut m = Granulator::new(FS);
+        m.set_audio_buffer(&buffer);
+        m.set_auto_emission(false);
+
+        m.trigger_at_sample(10).unwrap();
+        let mut block = [0_f32; 64];
+        m.process_block(&mut block);
+
+        // the envelope starts at 0 on the first sample of the grain
+        assert!(block[..11].iter().all(|sample| *sample == 0.0));
+        assert!(block[11] > 0.0);
+    }
+
+    #[test]
+    fn trigger_along_the_auto_emission() {
+        let buffer = [1_f32; 1000];
+        let mut m = Granulator::new(FS);
+        m.set_audio_buffer(&buffer);
+        m.set_active_grains(2);
+
+        let mut block = [0_f32; 64];
+        m.process_block(&mut block);
+        assert!(m.grains.get_grains().len() == 2);
+
+        // triggered grains play on top of the automatically started ones
+        m.trigger_burst(3).unwrap();
+        m.process_block(&mut block);
+        assert!(m.grains.get_grains().len() == 5);
+    }
+
+    #[test]
+    fn keep_the_density_while_a_trigger_is_pending() {
+        let buffer = [1_f32; 1000];
+        let mut m = Granulator::new(FS);
+        m.set_audio_buffer(&buffer);
+        m.set_emission_mode(EmissionMode::Synchronous);
+        m.set_density(100.0);
+        m.set_active_grains(MAX_GRAINS);
+
+        m.trigger_at_sample(24_000).unwrap();
+        let mut block = [0_f32; 480];
+        for _ in 0..40 {
+            m.process_block(&mut block);
+        }
+
+        // a grain every 480 samples, the trigger is still pending
+        let emitted = m.current_id_counter - 1;
+        assert!((40..=41).contains(&emitted), "{emitted}");
+        assert!(m.scheduler.future_vector.len() == 2);
+    }
+
+    #[test]
+    fn spawn_a_grain_with_exact_parameters() {
+        let buffer = [1_f32; 1000];
+        let mut m = Granulator::new(FS);
+        m.set_audio_buffer(&buffer);
+        m.set_auto_emission(false);
+
+        // spread parameters don't apply to spawned grains
+        m.set_parameter(VelocitySpread, 1.0);
+        m.set_parameter(PanSpread, 1.0);
+
+        let spec = GrainSpec {
+            offset: 100,
+            length: 20,
+            velocity: 0.5,
+            pan: 0.0,
+            window: WindowFunction::Rectangular,
+            delay: Duration::from_secs_f32(10.5 / FS as f32),
+            ..Default::default()
+        };
+        let id = m.spawn_grain(spec).unwrap();
+        assert!(m.spawn_grain(spec).unwrap() != id);
+
+        let (mut left, mut right) = ([0_f32; 64], [0_f32; 64]);
+        m.process_stereo_block(&mut left, &mut right);
+
+        // both grains start after the delay, play for their length and are panned to the left
+        assert!(left[..10].iter().all(|sample| *sample == 0.0));
+        assert!(left[11..30]
+            .iter()
+            .all(|sample| *sample > 0.0 && *sample == left[11]));
+        assert!(left[30..].iter().all(|sample| *sample == 0.0));
+        assert!(right.iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn spawn_a_grain_with_its_own_interpolation() {
+        let buffer: [f32; 1000] = core::array::from_fn(|i| i as f32 / 1000.0);
+        let mut m = Granulator::new(FS);
+        m.set_audio_buffer(&buffer);
+        m.set_auto_emission(false);
+        m.set_interpolation(Interpolation::Linear);
+
+        // half the speed reads in between every two samples
+        let spec = GrainSpec {
+            offset: 100,
+            length: 40,
+            pitch: 0.5,
+            window: WindowFunction::Rectangular,
+            ..Default::default()
+        };
+        let mut output = [0_f32; 64];
+
+        m.spawn_grain(GrainSpec {
+            interpolation: Some(Interpolation::None),
+            ..spec
+        })
+        .unwrap();
+        m.process_block(&mut output);
+        assert!(output[1..39]
+            .chunks(2)
+            .all(|pair| pair[0] > 0.0 && pair[0] == pair[1]));
+
+        // without its own interpolation, the grain uses the one of the granulator
+        m.spawn_grain(spec).unwrap();
+        m.process_block(&mut output);
+        assert!(output[1..40].windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn refuse_to_spawn_grains() {
+        let buffer = [1_f32; 1000];
+        let mut m = Granulator::new(FS);
+        assert!(m.spawn_grain(GrainSpec::default()) == Err(SpawnError::NoAudioBuffer));
+
+        m.set_audio_buffer(&buffer);
+        m.set_auto_emission(false);
+        for _ in 0..MAX_GRAINS {
+            m.spawn_grain(GrainSpec::default()).unwrap();
+        }
+        assert!(m.spawn_grain(GrainSpec::default()) == Err(SpawnError::SchedulerFull));
+
+        for pitch in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            let spec = GrainSpec {
+                pitch,
+                ..Default::default()
+            };
+            assert!(m.spawn_grain(spec) == Err(SpawnError::InvalidPitch));
+        }
+        let spec = GrainSpec {
+            length: 0,
+            ..Default::default()
+        };
+        assert!(m.spawn_grain(spec) == Err(SpawnError::ZeroLength));
+    }
+
+    #[test]
+    fn follow_a_spawned_grain() {
+        let buffer = [1_f32; 1000];
+        let mut m = Granulator::new(FS);
+        m.set_audio_buffer(&buffer);
+        m.set_auto_emission(false);
+        m.set_emission_mode(EmissionMode::Synchronous);
+        m.set_active_grains(1);
+
+        let spec = GrainSpec {
+            length: 100,
+            delay: Duration::from_secs_f32(50.0 / FS as f32),
+            ..Default::default()
+        };
+        let id = m.spawn_grain(spec).unwrap();
+        assert!(m.get_grain_state(id) == GrainState::Scheduled);
+
+        m.process_block(&mut [0_f32; 64]);
+        assert!(m.get_grain_state(id) == GrainState::Playing);
+        m.process_block(&mut [0_f32; 100]);
+        assert!(m.get_grain_state(id) == GrainState::Gone);
+
+        // grains above the voice limit are skipped
+        let playing = m.spawn_grain(GrainSpec::default()).unwrap();
+        let skipped = m.spawn_grain(GrainSpec::default()).unwrap();
+        m.process_block(&mut [0_f32; 64]);
+        assert!(m.get_grain_state(playing) == GrainState::Playing);
+        assert!(m.get_grain_state(skipped) == GrainState::Gone);
+    }
+
+    #[test]
+    fn cancel_spawned_grains() {
+        let buffer = [1_f32; 1000];
+        let mut m = Granulator::new(FS);
+        m.set_audio_buffer(&buffer);
+        m.set_auto_emission(false);
+
+        let scheduled = m
+            .spawn_grain(GrainSpec {
+                delay: Duration::from_millis(10),
+                ..Default::default()
+            })
+            .unwrap();
+        m.cancel_grain(scheduled).unwrap();
+        assert!(m.get_grain_state(scheduled) == GrainState::Gone);
+        assert!(m.cancel_grain(scheduled) == Err(scheduled));
+
+        // playing grains fade out
+        let spec = GrainSpec {
+            window: WindowFunction::Rectangular,
+            ..Default::default()
+        };
+        let playing = m.spawn_grain(spec).unwrap();
+        m.process_block(&mut [0_f32; 64]);
+        m.cancel_grain(playing).unwrap();
+
+        let mut block = [0_f32; 240];
+        m.process_block(&mut block);
+        assert!(block[..239].windows(2).all(|pair| pair[1] < pair[0]));
+        assert!(m.get_grain_state(playing) == GrainState::Gone);
+    }
+
+    #[test]
+    fn cap_the_voices_of_a_fixed_density() {
+        let buffer = [0_f32; 48_000];
+        let mut m = Granulator::new(FS);
+        m.rng = Rand32::new(1);
+        m.set_audio_buffer(&buffer);
+        m.set_emission_mode(EmissionMode::Synchronous);
+        m.set_parameter(Density, 1.0);
+        m.set_parameter(DensitySpread, 0.5);
+        m.set_active_grains(4);
+        m.set_grain_size(100.0);
+
+        let mut block = [0_f32; 64];
+        for _ in 0..750 {
+            m.process_block(&mut block);
+            assert!(m.grains.get_grains().len() <= 4);
+        }
+
+        // about 1000 grains per second were emitted, even though only 4 of them can play
+        let emitted = m.current_id_counter;
+        assert!((700..1300).contains(&emitted), "{emitted}");
+        assert!(m.grains.get_grains().len() == 4);
+    }
+
+    #[test]
+    fn filter_pitched_up_grains() {
+        // close to the nyquist frequency, aliases when being pitched up
+        let buffer: [f32; 10_000] =
+            core::array::from_fn(|i| (core::f32::consts::TAU * i as f32 / 2.5).sin());
+        let mut storage = [0_f32; 10_000];
+
+        let mut m = Granulator::new(FS);
+        m.set_mip_map_storage(&mut storage);
+        m.set_audio_buffer(&buffer);
+        m.set_master_volume(1.0);
+        m.settings.pitch = 4.0;
+
+        // away from the edges of the buffer
+        m.settings.offset = 5_000;
+
+        // the octaves are being filtered during the first blocks
+        let mut output = [0.0; 480];
+        for _ in 0..3 {
+            m.process_block(&mut output);
+        }
+        assert!(m.mip_map_builder.is_none());
+
+        m.grains.flush();
+        m.process_block(&mut output);
+        assert!(output.iter().all(|sample| sample.abs() < 0.01));
+
+        // the grain still plays as long as without the mip map
+        assert!(m.grains.samples_until_next_finish() == Some(1));
+
+        m.disable_mip_map();
+        m.grains.flush();
+        m.process_block(&mut output);
+        assert!(output.iter().any(|sample| sample.abs() > 0.1));
+    }
+
+    #[test]
+    fn keep_the_mip_map_until_its_grains_finish() {
+        let first_buffer = [0.5_f32; 10_000];
+        let second_buffer = [0_f32; 10_000];
+        let mut storage = [0_f32; 10_000];
+
+        let mut m = Granulator::new(FS);
+        m.set_mip_map_storage(&mut storage);
+        m.set_audio_buffer(&first_buffer);
+        m.set_active_grains(1);
+        m.settings.pitch = 4.0;
+        m.settings.grain_size_in_samples = 2_400;
+
+        let mut output = [0.0; 480];
+        for _ in 0..3 {
+            m.process_block(&mut output);
+        }
+        m.grains.flush();
+        m.process_block(&mut output);
+        assert!(m.mip_map.contains(&m.grains.get_grains()[0]));
+
+        // the playing grain keeps on reading the octave of the first buffer
+        m.set_audio_buffer(&second_buffer);
+        assert!(m.mip_map_builder.is_some());
+        for _ in 0..3 {
+            m.process_block(&mut output);
+            assert!(!m.is_previous_buffer_released());
+        }
+        assert!(output.iter().all(|sample| *sample > 0.0));
+
+        // the octaves of the second buffer are filtered after it has finished
+        m.set_active_grains(0);
+        m.process_block(&mut [0.0; 1000]);
+        assert!(m.is_previous_buffer_released());
+        for _ in 0..3 {
+            m.process_block(&mut output);
+        }
+        assert!(m.mip_map_builder.is_none());
+        assert!(storage[..2_500].iter().all(|sample| *sample == 0.0));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn keep_shared_buffers_alive() {
+        let mut m = Granulator::new(FS);
+        m.set_active_grains(MAX_GRAINS);
+
+        let buffer: Arc<[f32]> = std::vec![1.0; 10_000].into();
+        m.set_shared_audio_buffer(buffer.clone(), 1).unwrap();
+        m.process_block(&mut [0.0; 64]);
+
+        // grains don't hold on to the buffer themselves
+        assert!(Arc::strong_count(&buffer) == 2);
+
+        // the granulator keeps it while grains are still reading from it
+        m.set_shared_audio_buffer(std::vec![0.0; 10_000].into(), 1)
+            .unwrap();
+        assert!(m.take_released_buffer().is_none());
+        assert!(Arc::strong_count(&buffer) == 2);
+
+        // and hands it back once all of them are gone
+        m.grains.flush();
+        let released = m.take_released_buffer().unwrap();
+        assert!(Arc::ptr_eq(&released, &buffer));
+        assert!(m.take_released_buffer().is_none());
+
+        drop(released);
+        assert!(Arc::strong_count(&buffer) == 1);
+    }
+
+    #[test]
+    fn choose_random_channels() {
+        let mut m = Granulator::new(FS);
+        let buffer = [0_f32; 1000];
+        m.set_interleaved_audio_buffer(&buffer, 4).unwrap();
+        m.set_channel_mode(ChannelMode::Random);
+
+        let mut chosen = [false; 4];
+        for _ in 0..1000 {
+            chosen[m.get_new_channel().unwrap()] = true;
+        }
+        assert!(chosen == [true; 4]);
+    }
+
+    #[test]
+    fn play_stereo_buffer() {
+        let mut m = Granulator::new(FS);
+        let mut buffer = [0_f32; 20000];
+        for frame in buffer.chunks_mut(2) {
+            frame[1] = 1.0;
+        }
+        m.set_interleaved_audio_buffer(&buffer, 2).unwrap();
+        m.set_active_grains(MAX_GRAINS);
+        m.set_grain_size(10.0);
+        m.set_parameter(OffsetSpread, 1.0);
+
+        let mut left = [0_f32; 64];
+        let mut right = [0_f32; 64];
+        let mut right_sum = 0.0;
+        for _ in 0..20 {
+            m.process_stereo_block(&mut left, &mut right);
+            assert!(left.iter().all(|sample| sample.abs() < 0.001));
+            right_sum += right.iter().sum::<f32>();
+        }
+        assert!(right_sum > 0.0);
     }
 
     #[test]
@@ -640,7 +3006,7 @@ mod tests {
         m.set_grain_size(100.0);
         m.set_active_grains(1);
 
-        assert!(m.grain_size_in_samples == 4800);
+        assert!(m.settings.grain_size_in_samples == 4800);
     }
 
     #[test]
@@ -0,0 +1,300 @@
+use super::grain::Grain;
+use super::pointer_wrapper::BufferSlice;
+
+use crate::sample::Sample;
+
+use heapless::Vec;
+
+#[cfg(feature = "no_std")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+/// The most octaves an audio buffer is being filtered down by. Covers pitch ratios up to 16.
+pub const MAX_MIP_LEVELS: usize = 4;
+
+/// Coefficients of a half-band lowpass (27 taps, Blackman windowed sinc) at the odd distances
+/// 1, 3, 5, ... from its center. All even distances except the center itself are 0.
+const HALF_BAND: [f32; 6] = [
+    0.310_763_55,
+    -0.085_294_96,
+    0.034_212_8,
+    -0.012_825_706,
+    0.003_805_486,
+    -0.000_657_363,
+];
+const HALF_BAND_CENTER: f32 = 0.5;
+
+/// Octaves of an audio buffer, each one half-band filtered and decimated to half the length of
+/// the previous one. Grains with a pitch above 1 read from the level matching their pitch, so
+/// they don't alias.
+#[derive(Debug)]
+pub struct MipMap<T: Sample> {
+    levels: Vec<BufferSlice<T>, MAX_MIP_LEVELS>, // starting with the first octave
+}
+
+impl<T: Sample> MipMap<T> {
+    pub fn new() -> Self {
+        MipMap { levels: Vec::new() }
+    }
+
+    /// Returns the level for the given pitch ratio together with its number, where level 0 is the
+    /// audio buffer itself. Falls back to the highest level that has been built.
+    pub fn get_level(&self, pitch: f32) -> Option<(usize, &BufferSlice<T>)> {
+        if pitch <= 1.0 {
+            return None;
+        }
+
+        let level = (pitch.log2().ceil() as usize).min(self.levels.len());
+        self.levels
+            .get(level.checked_sub(1)?)
+            .map(|slice| (level, slice))
+    }
+
+    /// Returns whether the grain is reading from any of the levels.
+    pub fn contains(&self, grain: &Grain<T>) -> bool {
+        self.levels.iter().any(|level| grain.reads_from(level))
+    }
+}
+
+/// Filters an audio buffer into the octaves of a mip map bit by bit, so the work can be spread
+/// across several audio blocks instead of stalling the one that sets the buffer.
+#[derive(Debug)]
+pub struct MipMapBuilder<T: Sample> {
+    source: BufferSlice<T>,
+    storage: BufferSlice<T>,
+    levels: Vec<BufferSlice<T>, MAX_MIP_LEVELS>, // levels that have been filtered completely
+    level: Option<BufferSlice<T>>,               // level that is being filtered
+    frame: usize,                                // next frame of that level
+    used: usize,                                 // samples of the storage in use
+}
+
+impl<T: Sample> MipMapBuilder<T> {
+    /// Prepares filtering the given buffer into as many octaves as fit into the storage. Nothing
+    /// is being written before the first call of `build`.
+    pub fn new(source: &BufferSlice<T>, storage: &BufferSlice<T>) -> Self {
+        MipMapBuilder {
+            source: source.get_wrapping_sub_slice(0, source.length),
+            storage: storage.get_wrapping_sub_slice(0, storage.length),
+            levels: Vec::new(),
+            level: None,
+            frame: 0,
+            used: 0,
+        }
+    }
+
+    /// Filters up to `frames` frames into the storage. Returns whether all octaves are done.
+    pub fn build(&mut self, mut frames: usize) -> bool {
+        loop {
+            if self.level.is_none() {
+                self.level = self.next_level();
+            }
+            let Some(level) = &self.level else {
+                return true;
+            };
+            if frames == 0 {
+                return false;
+            }
+
+            let previous = self.levels.last().unwrap_or(&self.source);
+            let channels = level.channels;
+            let end = level.length.min(self.frame + frames);
+            for frame in self.frame..end {
+                for channel in 0..channels {
+                    let value = filter_at(previous, 2 * frame, channel);
+
+                    // the level lies within the storage
+                    unsafe {
+                        level
+                            .ptr
+                            .write(frame * channels + channel, T::from_f32(value))
+                    };
+                }
+            }
+            frames -= end - self.frame;
+            self.frame = end;
+
+            if self.frame == level.length {
+                self.used += level.length * channels;
+                self.levels.extend(self.level.take());
+                self.frame = 0;
+            }
+        }
+    }
+
+    /// Returns the mip map of all octaves that have been filtered.
+    pub fn finish(self) -> MipMap<T> {
+        MipMap {
+            levels: self.levels,
+        }
+    }
+
+    /// Returns the next octave to be filtered, if there is one and it fits into the storage.
+    fn next_level(&self) -> Option<BufferSlice<T>> {
+        let previous = self.levels.last().unwrap_or(&self.source);
+        let channels = self.source.channels;
+        let frames = previous.length.div_ceil(2);
+        if self.levels.is_full()
+            || frames < 2
+            || self.used + frames * channels > self.storage.length
+        {
+            return None;
+        }
+
+        Some(
+            self.storage
+                .get_interleaved_sub_slice(self.used, frames, channels),
+        )
+    }
+}
+
+/// Applies the half-band lowpass at the given frame. Frames outside of the buffer are being
+/// truncated to its first and last one.
+fn filter_at<T: Sample>(buffer: &BufferSlice<T>, frame: usize, channel: usize) -> f32 {
+    let mut value = HALF_BAND_CENTER * buffer.get_f32_value_at(&mut frame.clone(), channel);
+
+    for (i, coefficient) in HALF_BAND.iter().enumerate() {
+        let distance = 2 * i + 1;
+        let mut before = frame.saturating_sub(distance);
+        let mut after = frame + distance;
+
+        value += coefficient
+            * (buffer.get_f32_value_at(&mut before, channel)
+                + buffer.get_f32_value_at(&mut after, channel));
+    }
+
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f32::consts::TAU;
+
+    fn sine(period: f32) -> [f32; 1024] {
+        core::array::from_fn(|i| (TAU * i as f32 / period).sin())
+    }
+
+    fn build<T: Sample>(buffer: &BufferSlice<T>, storage: &BufferSlice<T>) -> MipMap<T> {
+        let mut builder = MipMapBuilder::new(buffer, storage);
+        builder.build(usize::MAX);
+        builder.finish()
+    }
+
+    fn peak<T: Sample>(slice: &BufferSlice<T>) -> f32 {
+        // skip the edges of the buffer
+        (32..slice.length - 32)
+            .map(|mut i| slice.get_f32_value_at(&mut i, 0).abs())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn build_all_levels() {
+        let buffer = [0_f32; 1000];
+        let mut storage = [0_f32; 1000];
+
+        let mip_map = build(
+            &BufferSlice::from_slice(&buffer),
+            &BufferSlice::from_mut_slice(&mut storage),
+        );
+
+        assert!(mip_map.levels.len() == MAX_MIP_LEVELS);
+        assert!(mip_map.levels[0].length == 500);
+        assert!(mip_map.levels[3].length == 63);
+    }
+
+    #[test]
+    fn build_as_many_levels_as_fit() {
+        let buffer = [0_f32; 1000];
+        let mut storage = [0_f32; 800];
+
+        let mip_map = build(
+            &BufferSlice::from_slice(&buffer),
+            &BufferSlice::from_mut_slice(&mut storage),
+        );
+
+        assert!(mip_map.levels.len() == 2);
+        assert!(mip_map.get_level(10.0).unwrap().0 == 2);
+    }
+
+    #[test]
+    fn choose_level_by_pitch() {
+        let buffer = [0_f32; 1000];
+        let mut storage = [0_f32; 1000];
+
+        let mip_map = build(
+            &BufferSlice::from_slice(&buffer),
+            &BufferSlice::from_mut_slice(&mut storage),
+        );
+
+        assert!(mip_map.get_level(0.5).is_none());
+        assert!(mip_map.get_level(1.0).is_none());
+        assert!(mip_map.get_level(1.5).unwrap().0 == 1);
+        assert!(mip_map.get_level(2.0).unwrap().0 == 1);
+        assert!(mip_map.get_level(3.0).unwrap().0 == 2);
+        assert!(mip_map.get_level(10.0).unwrap().0 == 4);
+    }
+
+    #[test]
+    fn filter_out_high_frequencies() {
+        let mut storage = [0_f32; 1024];
+
+        // a quarter of the sample rate passes the first octave
+        let low = sine(16.0);
+        let mip_map = build(
+            &BufferSlice::from_slice(&low),
+            &BufferSlice::from_mut_slice(&mut storage),
+        );
+        assert!(peak(&mip_map.levels[0]) > 0.99);
+
+        // but the upper half of the spectrum would alias
+        let high = sine(2.5);
+        let mip_map = build(
+            &BufferSlice::from_slice(&high),
+            &BufferSlice::from_mut_slice(&mut storage),
+        );
+        assert!(peak(&mip_map.levels[0]) < 0.01);
+    }
+
+    #[test]
+    fn build_bit_by_bit() {
+        let buffer = sine(16.0);
+        let mut storage = [0_f32; 1024];
+        let mut complete = [0_f32; 1024];
+        build(
+            &BufferSlice::from_slice(&buffer),
+            &BufferSlice::from_mut_slice(&mut complete),
+        );
+
+        let mut builder = MipMapBuilder::new(
+            &BufferSlice::from_slice(&buffer),
+            &BufferSlice::from_mut_slice(&mut storage),
+        );
+        assert!(!builder.build(0));
+        assert!(!builder.build(500));
+        assert!(builder.levels.is_empty());
+
+        let mut calls = 0;
+        while !builder.build(100) {
+            calls += 1;
+        }
+        assert!(calls == 4);
+        assert!(builder.finish().levels.len() == MAX_MIP_LEVELS);
+        assert!(storage == complete);
+    }
+
+    #[test]
+    fn keep_the_channels_apart() {
+        let buffer: [f32; 1000] = core::array::from_fn(|i| (i % 2) as f32);
+        let mut storage = [0_f32; 1000];
+
+        let mip_map = build(
+            &BufferSlice::from_interleaved_slice(&buffer, 2),
+            &BufferSlice::from_mut_slice(&mut storage),
+        );
+
+        let level = &mip_map.levels[0];
+        assert!(level.channels == 2 && level.length == 250);
+        assert!(level.get_f32_value_at(&mut 100, 0).abs() < 0.001);
+        assert!((level.get_f32_value_at(&mut 100, 1) - 1.0).abs() < 0.001);
+    }
+}
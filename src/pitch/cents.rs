@@ -1,5 +1,6 @@
 use super::hz::{AudioFrequencies, Hz};
 
+#[cfg(feature = "no_std")]
 #[allow(unused)]
 use micromath::F32Ext;
 
@@ -19,6 +20,7 @@ const EMPTY_12TET_INTERVAL: CentsInterval = CentsInterval {
 #[derive(Debug, Clone, Copy)]
 pub struct ET(u32);
 
+#[allow(unused)]
 pub trait EqualTemperment {
     fn tet(&self) -> ET;
 }
@@ -28,7 +30,7 @@ impl EqualTemperment for u32 {
         if self < &2 {
             ET(2)
         } else {
-            ET(self.clone())
+            ET(*self)
         }
     }
 }
@@ -97,15 +99,15 @@ impl CentsInterval {
     }
 
     #[allow(unused)]
-    pub fn to_hz(&self, from: Hz) -> Hz {
-        (2.0.powf(self.cents / CENT_OCTAVE as f32) * from.0).hz()
+    pub fn to_hz(self, from: Hz) -> Hz {
+        (2.0_f32.powf(self.cents / CENT_OCTAVE as f32) * from.0).hz()
     }
 
     #[allow(unused)]
     fn f32(&self) -> f32 {
-        (self.octave as i32 * CENT_OCTAVE as i32
-            + self.semitone as i32 * (CENT_OCTAVE as f32 / self.tet.0 as f32) as i32
-            + self.rest as i32) as f32
+        (self.octave * CENT_OCTAVE as i32
+            + self.semitone * (CENT_OCTAVE as f32 / self.tet.0 as f32) as i32
+            + self.rest) as f32
     }
 
     #[allow(unused)]
@@ -150,6 +152,6 @@ impl CentsInterval {
 
     #[inline(always)]
     pub fn ratio(&self) -> f32 {
-        2.0.powf(self.cents / CENT_OCTAVE as f32)
+        2.0_f32.powf(self.cents / CENT_OCTAVE as f32)
     }
 }
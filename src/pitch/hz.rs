@@ -2,6 +2,7 @@ use super::cents::{CentsInterval, CENT_OCTAVE, ET};
 
 use core::f32::consts::LN_2;
 
+#[cfg(feature = "no_std")]
 #[allow(unused)]
 use micromath::F32Ext;
 
@@ -2,11 +2,14 @@ pub(crate) mod cents;
 pub(crate) mod hz;
 pub(crate) mod scales;
 
+pub use scales::get_ratios_for;
+#[allow(unused_imports)]
+pub use scales::get_semitones_for;
 pub use scales::HeptatonicRatios;
-pub use scales::{get_ratios_for, get_semitones_for};
 pub use scales::{ModeType, ScaleType};
 
 use core::ops::Neg;
+#[cfg(feature = "no_std")]
 #[allow(unused)]
 use micromath::F32Ext;
 
@@ -1,22 +1,31 @@
+use crate::sample::Sample;
 use core::ops::Deref;
-use num_traits::AsPrimitive;
 
 /// Raw pointer that implements the `Send` trait since it's only acting on static memory
 ///
 /// Should always point at the beginning of your audio buffer in use
 #[derive(Debug)]
-pub struct BufferPointer<T: AsPrimitive<f32>>(pub *const T);
-unsafe impl<T: AsPrimitive<f32>> Send for BufferPointer<T> {}
+pub struct BufferPointer<T: Sample>(pub *const T);
+unsafe impl<T: Sample> Send for BufferPointer<T> {}
 
-impl<T: AsPrimitive<f32>> BufferPointer<T> {
+impl<T: Sample> BufferPointer<T> {
     pub fn add(&self, offset: usize) -> BufferPointer<T> {
         unsafe { BufferPointer(self.0.add(offset)) }
     }
+
+    /// Writes a value into the buffer at the given offset.
+    ///
+    /// # Safety
+    /// The pointer has to be created from mutable memory and the offset has to lie within the
+    /// buffer it is pointing at.
+    pub unsafe fn write(&self, offset: usize, value: T) {
+        (self.0 as *mut T).add(offset).write(value)
+    }
 }
 
 /// Since we know that our pointer is always pointing at some buffer in memory, it can
 /// never be dangling. Thats's why it is safe to dereference it with `unsafe`.
-impl<T: AsPrimitive<f32>> Deref for BufferPointer<T> {
+impl<T: Sample> Deref for BufferPointer<T> {
     type Target = T;
 
     fn deref(&self) -> &T {
@@ -24,24 +33,52 @@ impl<T: AsPrimitive<f32>> Deref for BufferPointer<T> {
     }
 }
 
+/// Slice of an audio buffer in memory. If the slice reaches over the end of the buffer, it wraps
+/// around to its beginning, which is used for circular buffers.
+///
+/// Multi-channel buffers are interleaved, so all positions and lengths are counted in frames.
 #[derive(Debug)]
-pub struct BufferSlice<T: AsPrimitive<f32>> {
-    pub ptr: BufferPointer<T>,
+pub struct BufferSlice<T: Sample> {
+    pub ptr: BufferPointer<T>, // points to the beginning of the whole buffer
+    pub start: usize,
     pub length: usize,
+    pub buffer_length: usize,
+    pub channels: usize,
 }
 
-impl<T: AsPrimitive<f32>> BufferSlice<T> {
+impl<T: Sample> BufferSlice<T> {
     pub fn from_slice(slice: &[T]) -> BufferSlice<T> {
+        BufferSlice::from_interleaved_slice(slice, 1)
+    }
+
+    /// Creates a slice of an interleaved buffer with the given amount of channels. An incomplete
+    /// frame at the end of the buffer is being ignored.
+    pub fn from_interleaved_slice(slice: &[T], channels: usize) -> BufferSlice<T> {
+        let channels = channels.max(1);
         BufferSlice {
             ptr: BufferPointer(slice.as_ptr()),
+            start: 0,
+            length: slice.len() / channels,
+            buffer_length: slice.len() / channels,
+            channels,
+        }
+    }
+
+    /// Same as `from_slice`, but the memory may be written to through `BufferPointer::write`.
+    pub fn from_mut_slice(slice: &mut [T]) -> BufferSlice<T> {
+        BufferSlice {
+            ptr: BufferPointer(slice.as_mut_ptr()),
+            start: 0,
             length: slice.len(),
+            buffer_length: slice.len(),
+            channels: 1,
         }
     }
 
     pub fn get_sub_slice(&self, offset: &mut usize, length: &mut usize) -> BufferSlice<T> {
         // truncate offset if too far
         if *offset >= self.length {
-            *offset = self.length - 1;
+            *offset = self.length.saturating_sub(1);
         }
 
         // afterwards truncate length if too long
@@ -49,13 +86,108 @@ impl<T: AsPrimitive<f32>> BufferSlice<T> {
             *length = self.length - *offset;
         }
 
+        self.get_wrapping_sub_slice(*offset, *length)
+    }
+
+    /// Returns a sub slice which wraps around the end of the underlying buffer instead of being
+    /// truncated. The length can never exceed the length of the whole buffer.
+    pub fn get_wrapping_sub_slice(&self, offset: usize, length: usize) -> BufferSlice<T> {
+        BufferSlice {
+            ptr: BufferPointer(self.ptr.0),
+            start: (self.start + offset) % self.buffer_length.max(1),
+            length: length.min(self.buffer_length),
+            buffer_length: self.buffer_length,
+            channels: self.channels,
+        }
+    }
+
+    /// Returns a slice of `length` interleaved frames with the given amount of channels, which
+    /// starts `offset` samples into the buffer. Used to split up storage buffers.
+    pub fn get_interleaved_sub_slice(
+        &self,
+        offset: usize,
+        length: usize,
+        channels: usize,
+    ) -> BufferSlice<T> {
         BufferSlice {
-            ptr: self.ptr.add(*offset),
-            length: *length,
+            ptr: self.ptr.add(offset),
+            start: 0,
+            length,
+            buffer_length: length,
+            channels,
+        }
+    }
+
+    /// Returns whether both slices point into the same buffer.
+    pub fn shares_buffer_with(&self, other: &BufferSlice<T>) -> bool {
+        core::ptr::eq(self.ptr.0, other.ptr.0)
+    }
+
+    /// Index of the frame at the given position of the slice within the whole buffer.
+    pub fn buffer_index(&self, position: usize) -> usize {
+        let index = self.start + position;
+        if index >= self.buffer_length {
+            index - self.buffer_length
+        } else {
+            index
         }
     }
 
-    pub fn get_f32_value_at(&self, position: &mut usize) -> f32 {
-        self.get_sub_slice(position, &mut 1).ptr.as_()
+    pub fn get_f32_value_at(&self, position: &mut usize, channel: usize) -> f32 {
+        // truncate position if too far
+        if *position >= self.length {
+            *position = self.length.saturating_sub(1);
+        }
+
+        let frame = self.buffer_index(*position) * self.channels;
+        self.ptr
+            .add(frame + channel.min(self.channels - 1))
+            .to_f32()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_around_the_end() {
+        let buffer = [0_f32, 1.0, 2.0, 3.0, 4.0];
+        let slice = BufferSlice::from_slice(&buffer);
+
+        let sub_slice = slice.get_wrapping_sub_slice(3, 4);
+
+        let values = [0, 1, 2, 3].map(|mut i| sub_slice.get_f32_value_at(&mut i, 0));
+        assert!(values == [3.0, 4.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn read_interleaved_frames() {
+        let buffer = [0_f32, 10.0, 1.0, 11.0, 2.0, 12.0, 3.0];
+        let slice = BufferSlice::from_interleaved_slice(&buffer, 2);
+
+        assert!(slice.length == 3);
+
+        let (mut offset, mut length) = (1, 2);
+        let sub_slice = slice.get_sub_slice(&mut offset, &mut length);
+
+        assert!(sub_slice.get_f32_value_at(&mut 0, 0) == 1.0);
+        assert!(sub_slice.get_f32_value_at(&mut 0, 1) == 11.0);
+        assert!(sub_slice.get_f32_value_at(&mut 1, 1) == 12.0);
+
+        // the channel is truncated as well
+        assert!(sub_slice.get_f32_value_at(&mut 5, 2) == 12.0);
+    }
+
+    #[test]
+    fn truncate_sub_slice() {
+        let buffer = [0_f32, 1.0, 2.0, 3.0, 4.0];
+        let slice = BufferSlice::from_slice(&buffer);
+
+        let (mut offset, mut length) = (3, 4);
+        let sub_slice = slice.get_sub_slice(&mut offset, &mut length);
+
+        assert!(length == 2);
+        assert!(sub_slice.get_f32_value_at(&mut 5, 0) == 4.0);
     }
 }
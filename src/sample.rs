@@ -0,0 +1,155 @@
+/// Sample formats an audio buffer can be stored in.
+///
+/// Every format is being scaled to floating point values between -1.0 and 1.0 while playing, so
+/// audio buffers never have to be copied into `f32` buffers first.
+pub trait Sample: Copy + Send + Sync + 'static {
+    /// Converts the sample into a floating point value between -1.0 and 1.0.
+    fn to_f32(self) -> f32;
+
+    /// Converts a floating point value between -1.0 and 1.0 into the sample format.
+    fn from_f32(value: f32) -> Self;
+}
+
+/// Packed 24 bit signed integer sample in little endian byte order, as found in WAV files.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct I24(pub [u8; 3]);
+
+impl I24 {
+    pub const MAX: i32 = 8_388_607;
+    pub const MIN: i32 = -8_388_608;
+
+    /// Constructs the sample from the lower 24 bits of an `i32`.
+    pub fn from_i32(value: i32) -> Self {
+        let [b0, b1, b2, _] = value.to_le_bytes();
+        I24([b0, b1, b2])
+    }
+
+    /// Returns the sign extended sample value.
+    pub fn to_i32(self) -> i32 {
+        let [b0, b1, b2] = self.0;
+        i32::from_le_bytes([0, b0, b1, b2]) >> 8
+    }
+}
+
+impl Sample for f32 {
+    #[inline(always)]
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    #[inline(always)]
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+}
+
+impl Sample for f64 {
+    #[inline(always)]
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    #[inline(always)]
+    fn from_f32(value: f32) -> Self {
+        value as f64
+    }
+}
+
+impl Sample for i16 {
+    #[inline(always)]
+    fn to_f32(self) -> f32 {
+        self as f32 / 32_768.0
+    }
+
+    #[inline(always)]
+    fn from_f32(value: f32) -> Self {
+        (value.clamp(-1.0, 1.0) * 32_767.0) as i16
+    }
+}
+
+impl Sample for I24 {
+    #[inline(always)]
+    fn to_f32(self) -> f32 {
+        self.to_i32() as f32 / 8_388_608.0
+    }
+
+    #[inline(always)]
+    fn from_f32(value: f32) -> Self {
+        I24::from_i32((value.clamp(-1.0, 1.0) * I24::MAX as f32) as i32)
+    }
+}
+
+impl Sample for i32 {
+    #[inline(always)]
+    fn to_f32(self) -> f32 {
+        self as f32 / 2_147_483_648.0
+    }
+
+    #[inline(always)]
+    fn from_f32(value: f32) -> Self {
+        (value.clamp(-1.0, 1.0) as f64 * i32::MAX as f64) as i32
+    }
+}
+
+/// Unsigned 8 bit samples are centered around 128, as found in WAV files.
+impl Sample for u8 {
+    #[inline(always)]
+    fn to_f32(self) -> f32 {
+        (self as f32 - 128.0) / 128.0
+    }
+
+    #[inline(always)]
+    fn from_f32(value: f32) -> Self {
+        (value.clamp(-1.0, 1.0) * 127.0 + 128.0) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_scaling<T: Sample>(min: T, zero: T, max: T) {
+        assert!(min.to_f32() == -1.0);
+        assert!(zero.to_f32() == 0.0);
+        assert!(max.to_f32() <= 1.0 && max.to_f32() > 0.99);
+    }
+
+    #[test]
+    fn scale_integer_formats() {
+        check_scaling(i16::MIN, 0, i16::MAX);
+        check_scaling(
+            I24::from_i32(I24::MIN),
+            I24::default(),
+            I24::from_i32(I24::MAX),
+        );
+        check_scaling(i32::MIN, 0, i32::MAX);
+        check_scaling(u8::MIN, 128, u8::MAX);
+    }
+
+    #[test]
+    fn scale_float_formats() {
+        assert!((-1.0_f64).to_f32() == -1.0);
+        assert!(0.5_f64.to_f32() == 0.5);
+        assert!(0.5_f32.to_f32() == 0.5);
+    }
+
+    #[test]
+    fn convert_back_and_forth() {
+        for value in [-1.0, -0.5, 0.0, 0.25, 1.0] {
+            assert!((i16::from_f32(value).to_f32() - value).abs() < 0.001);
+            assert!((I24::from_f32(value).to_f32() - value).abs() < 0.001);
+            assert!((i32::from_f32(value).to_f32() - value).abs() < 0.001);
+            assert!((u8::from_f32(value).to_f32() - value).abs() < 0.01);
+            assert!((f64::from_f32(value).to_f32() - value).abs() < 0.001);
+        }
+    }
+
+    #[test]
+    fn sign_extend_i24() {
+        assert!(I24([0xff, 0xff, 0xff]).to_i32() == -1);
+        assert!(I24([0x00, 0x00, 0x80]).to_i32() == I24::MIN);
+        assert!(I24([0xff, 0xff, 0x7f]).to_i32() == I24::MAX);
+        assert!(I24::from_i32(-2).to_i32() == -2);
+    }
+}
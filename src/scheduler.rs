@@ -1,55 +1,243 @@
+use super::grain::GrainSpec;
 use super::manager::MAX_GRAINS;
+use crate::statistics::{get_random_bipolar_float, get_random_unipolar_float};
+use crate::step_pattern::StepPattern;
+use crate::tempo_clock::TempoClock;
 
-use core::time::Duration;
 use heapless::Vec;
+use oorandom::Rand32;
 
-#[derive(Debug)]
+#[cfg(feature = "no_std")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+/// Decides when new grains are being started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmissionMode {
+    /// Starts a new grain whenever one finishes, so `ActiveGrains` grains are playing at once
+    Refill,
+    /// Starts grains periodically at the rate of the `Density` parameter, no matter how long
+    /// they are. `DensitySpread` varies the rate from grain to grain. `ActiveGrains` only limits
+    /// how many of them play at once, grains starting above that limit are skipped.
+    Synchronous,
+    /// Like `Synchronous`, but every interval between two grains is jittered by up to the
+    /// `Jitter` parameter, relative to the mean interval
+    QuasiSynchronous,
+    /// Starts grains at random times, with exponentially distributed intervals between them
+    /// (a Poisson process), at the rate of the `Density` parameter on average
+    Asynchronous,
+    /// Starts grains on the steps of the tempo clock, counted either from the tempo or from the
+    /// ticks of an external clock. A pattern decides which steps start a grain, if one is set.
+    Tempo,
+}
+
+/// Returns the amount of samples until the next grain starts in the given emission mode, for the
+/// given mean interval in samples. The jitter (between 0 and 1) is only used by the
+/// `QuasiSynchronous` mode. Never returns less than a single sample.
+///
+/// The fraction of a sample every interval is cut short by is carried over to the next one in
+/// `remainder`, so the onsets don't drift away from the density.
+pub fn get_onset_interval(
+    mode: EmissionMode,
+    mean_interval: f32,
+    jitter: f32,
+    remainder: &mut f32,
+    rng: &mut Rand32,
+) -> u64 {
+    let interval = match mode {
+        // the tempo clock counts its own intervals
+        EmissionMode::Refill | EmissionMode::Synchronous | EmissionMode::Tempo => mean_interval,
+        EmissionMode::QuasiSynchronous => {
+            mean_interval * (1.0 + jitter.clamp(0.0, 1.0) * get_random_bipolar_float(rng))
+        }
+        EmissionMode::Asynchronous => {
+            // inverse transform sampling of the exponential distribution
+            -mean_interval * (1.0 - get_random_unipolar_float(rng)).ln()
+        }
+    } + *remainder;
+
+    // intervals stretched to a single sample can't be made up for
+    let samples = (interval as u64).max(1);
+    *remainder = (interval - samples as f32).max(0.0);
+    samples
+}
+
+/// Start of a scheduled grain in samples since the scheduler was created
+#[derive(Debug, Clone, Copy)]
 pub struct TimeInfo {
     pub id: usize,
-    start: Duration,
+    start: u64,
+    pub spec: Option<GrainSpec>, // parameters are spread randomly if `None`
+    pub accent: f32,             // scales the velocity, silent steps of a pattern are 0
+    pub automatic: bool,         // started by the emission mode instead of a trigger
 }
 
 impl TimeInfo {
-    fn new(id: usize, start: Duration) -> Self {
-        TimeInfo { id, start }
+    pub fn new(id: usize, start: u64, spec: Option<GrainSpec>) -> Self {
+        TimeInfo {
+            id,
+            start,
+            spec,
+            accent: 1.0,
+            automatic: false,
+        }
     }
 }
 
+/// Keeps track of when grains have to be started. All time values are counted in samples, so
+/// every grain can start on the exact sample it was scheduled for.
 #[derive(Debug)]
 pub struct Scheduler {
-    pub master_clock_counter: Duration,
+    pub master_clock_counter: u64,
     pub future_vector: Vec<TimeInfo, MAX_GRAINS>,
+    pub tempo_clock: TempoClock,
+    pub pattern: Option<StepPattern>, // rhythm on the steps of the tempo clock
+    pub remainder: f32, // fraction of a sample the previous onset intervals have been cut short by
+    pub previous_tick: Option<u64>, // sample the external clock ticked on last
 }
 
 impl Scheduler {
     pub fn new() -> Scheduler {
         Scheduler {
-            master_clock_counter: Duration::ZERO,
+            master_clock_counter: 0,
             future_vector: Vec::new(),
+            tempo_clock: TempoClock::new(),
+            pattern: None,
+            remainder: 0.0,
+            previous_tick: None,
         }
     }
 
-    pub fn update_clock(&mut self, time_step: Duration) -> Vec<usize, MAX_GRAINS> {
-        // increase counter by timestep
+    /// Advances the clock by `time_step` samples and returns the ids of all grains which are
+    /// due, in the order they have been scheduled.
+    #[cfg(test)]
+    pub fn update_clock(&mut self, time_step: u64) -> Vec<usize, MAX_GRAINS> {
+        self.advance_clock(time_step);
+        self.take_due_grains()
+            .iter()
+            .map(|time_info| time_info.id)
+            .collect()
+    }
+
+    pub fn advance_clock(&mut self, time_step: u64) {
         self.master_clock_counter += time_step;
+    }
 
+    /// Removes all grains which crossed their start time and returns them, in the order they
+    /// have been scheduled.
+    pub fn take_due_grains(&mut self) -> Vec<TimeInfo, MAX_GRAINS> {
+        let clock = self.master_clock_counter;
         let mut return_vec = Vec::new();
 
-        // assert if grains crossed the start time
-        for i in (0..self.future_vector.len()).rev() {
-            if self.future_vector[i].start <= self.master_clock_counter {
-                return_vec
-                    .push(self.future_vector.swap_remove(i).id)
-                    .unwrap();
+        self.future_vector.retain(|time_info| {
+            if time_info.start <= clock {
+                // both vectors share the same capacity
+                return_vec.push(*time_info).unwrap();
+                false
+            } else {
+                true
             }
-        }
+        });
 
         return_vec
     }
 
-    pub fn schedule_grain(&mut self, id: usize, delay: Duration) -> Result<(), TimeInfo> {
+    /// Returns the amount of samples until the next grain has to be started.
+    pub fn samples_until_next_grain(&self) -> Option<u64> {
+        self.future_vector
+            .iter()
+            .map(|time_info| time_info.start.saturating_sub(self.master_clock_counter))
+            .min()
+    }
+
+    pub fn schedule_grain(&mut self, id: usize, delay: u64) -> Result<(), TimeInfo> {
         self.future_vector
-            .push(TimeInfo::new(id, self.master_clock_counter + delay))
+            .push(TimeInfo::new(id, self.master_clock_counter + delay, None))
+    }
+
+    /// Schedules a grain of the emission mode, which is kept apart from triggered ones.
+    pub fn schedule_emission(&mut self, id: usize, delay: u64) -> Result<(), TimeInfo> {
+        self.future_vector.push(TimeInfo {
+            automatic: true,
+            ..TimeInfo::new(id, self.master_clock_counter + delay, None)
+        })
+    }
+
+    /// Returns how many grains of the emission mode are scheduled.
+    pub fn pending_emissions(&self) -> usize {
+        self.future_vector
+            .iter()
+            .filter(|time_info| time_info.automatic)
+            .count()
+    }
+
+    /// Scales the time until every grain of the emission mode starts by the given factor.
+    pub fn stretch_emissions(&mut self, factor: f32) {
+        let clock = self.master_clock_counter;
+        for time_info in self.future_vector.iter_mut() {
+            if time_info.automatic {
+                let remaining = time_info.start.saturating_sub(clock) as f32;
+                time_info.start = clock + (remaining * factor).round() as u64;
+            }
+        }
+    }
+
+    /// Removes all grains of the emission mode, triggered ones are kept.
+    pub fn clear_emissions(&mut self) {
+        self.future_vector.retain(|time_info| !time_info.automatic);
+    }
+
+    /// Schedules a grain which starts with the given parameters instead of spread ones.
+    pub fn schedule_grain_with_spec(
+        &mut self,
+        id: usize,
+        delay: u64,
+        spec: GrainSpec,
+    ) -> Result<(), TimeInfo> {
+        self.future_vector.push(TimeInfo::new(
+            id,
+            self.master_clock_counter + delay,
+            Some(spec),
+        ))
+    }
+
+    /// Schedules a grain on the next step of the pattern. Steps which don't start a grain by
+    /// chance are still scheduled, but silent, so the scheduler wakes up on every step. Every
+    /// step starts a grain without a pattern.
+    pub fn schedule_step(
+        &mut self,
+        id: usize,
+        delay: u64,
+        rng: &mut Rand32,
+    ) -> Result<(), TimeInfo> {
+        let accent = match &mut self.pattern {
+            Some(pattern) => {
+                let step = pattern.next_step();
+                match get_random_unipolar_float(rng) < step.probability {
+                    true => step.accent.clamp(0.0, 1.0),
+                    false => 0.0,
+                }
+            }
+            None => 1.0,
+        };
+
+        self.future_vector.push(TimeInfo {
+            accent,
+            automatic: true,
+            ..TimeInfo::new(id, self.master_clock_counter + delay, None)
+        })
+    }
+
+    pub fn remove_grain(&mut self, id: usize) -> Result<(), usize> {
+        for (vector_id, time_info) in self.future_vector.iter().enumerate() {
+            if time_info.id == id {
+                // keeps the others in the order they have been scheduled
+                self.future_vector.remove(vector_id);
+                return Ok(());
+            }
+        }
+
+        Err(id)
     }
 }
 
@@ -61,7 +249,7 @@ mod tests {
     fn schedule_a_grain() {
         let mut s = Scheduler::new();
 
-        s.schedule_grain(0, Duration::ZERO).unwrap();
+        s.schedule_grain(0, 0).unwrap();
 
         assert!(!s.future_vector.is_empty());
         assert!(s.future_vector[0].id == 0);
@@ -71,24 +259,191 @@ mod tests {
     fn update_the_clock() {
         let mut s = Scheduler::new();
 
-        s.schedule_grain(0, Duration::ZERO).unwrap();
+        s.schedule_grain(0, 0).unwrap();
 
-        let ids = s.update_clock(Duration::from_millis(10));
+        let ids = s.update_clock(480);
 
         assert!(!ids.is_empty());
         assert!(ids[0] == 0);
     }
 
+    #[test]
+    fn start_on_exact_sample() {
+        let mut s = Scheduler::new();
+
+        s.schedule_grain(0, 100).unwrap();
+        s.schedule_grain(1, 10).unwrap();
+
+        assert!(s.samples_until_next_grain() == Some(10));
+        assert!(s.update_clock(9).is_empty());
+        assert!(s.update_clock(1) == [1]);
+        assert!(s.samples_until_next_grain() == Some(90));
+        assert!(s.update_clock(89).is_empty());
+        assert!(s.update_clock(1) == [0]);
+        assert!(s.samples_until_next_grain().is_none());
+    }
+
+    #[test]
+    fn due_grains_keep_their_order() {
+        let mut s = Scheduler::new();
+
+        for id in 0..MAX_GRAINS {
+            s.schedule_grain(id, (id % 3) as u64).unwrap();
+        }
+
+        let ids = s.update_clock(2);
+        assert!(ids.len() == MAX_GRAINS);
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn keep_emissions_apart() {
+        let mut s = Scheduler::new();
+
+        s.schedule_grain(0, 10).unwrap();
+        s.schedule_emission(1, 20).unwrap();
+        s.schedule_step(2, 30, &mut Rand32::new(1)).unwrap();
+        assert!(s.pending_emissions() == 2);
+
+        // only the emissions are being stretched
+        s.stretch_emissions(0.5);
+        assert!(s.samples_until_next_grain() == Some(10));
+        assert!(s.update_clock(10) == [0, 1]);
+        assert!(s.samples_until_next_grain() == Some(5));
+
+        s.clear_emissions();
+        assert!(s.pending_emissions() == 0);
+        assert!(s.samples_until_next_grain().is_none());
+    }
+
+    #[test]
+    fn remove_a_grain_in_between() {
+        let mut s = Scheduler::new();
+
+        for id in 0..5 {
+            s.schedule_grain(id, 0).unwrap();
+        }
+        s.remove_grain(1).unwrap();
+        assert!(s.update_clock(0) == [0, 2, 3, 4]);
+    }
+
     #[test]
     fn remove_a_grain() {
         let mut s = Scheduler::new();
 
-        s.schedule_grain(0, Duration::ZERO).unwrap();
+        s.schedule_grain(0, 960).unwrap();
 
-        let ids = s.update_clock(Duration::from_millis(10));
+        let ids = s.update_clock(480);
 
+        assert!(ids.is_empty());
         assert!(!s.future_vector.is_empty());
-        s.remove_grain(ids[0]).unwrap();
+        s.remove_grain(0).unwrap();
         assert!(s.future_vector.is_empty());
     }
+
+    /// Draws 10_000 intervals with a mean of 480 samples from a fixed seed and returns them
+    /// together with their mean and standard deviation.
+    fn onset_intervals(mode: EmissionMode, jitter: f32) -> ([u64; 10_000], f64, f64) {
+        let mut rng = Rand32::new(1);
+        let mut remainder = 0.0;
+        let intervals: [u64; 10_000] = core::array::from_fn(|_| {
+            get_onset_interval(mode, 480.0, jitter, &mut remainder, &mut rng)
+        });
+
+        let mean = intervals.iter().sum::<u64>() as f64 / intervals.len() as f64;
+        let variance = intervals
+            .iter()
+            .map(|interval| (*interval as f64 - mean).powi(2))
+            .sum::<f64>()
+            / intervals.len() as f64;
+
+        (intervals, mean, variance.sqrt())
+    }
+
+    #[test]
+    fn emit_synchronously() {
+        let (intervals, _, _) = onset_intervals(EmissionMode::Synchronous, 0.5);
+        assert!(intervals.iter().all(|interval| *interval == 480));
+    }
+
+    #[test]
+    fn carry_the_fraction_of_a_sample() {
+        let mut rng = Rand32::new(1);
+        let mut remainder = 0.0;
+
+        // 1000 grains per second at 44.1 kHz
+        let intervals: [u64; 1000] = core::array::from_fn(|_| {
+            get_onset_interval(
+                EmissionMode::Synchronous,
+                44.1,
+                0.0,
+                &mut remainder,
+                &mut rng,
+            )
+        });
+        assert!(intervals
+            .iter()
+            .all(|interval| (44..=45).contains(interval)));
+        assert!((44_099..=44_100).contains(&intervals.iter().sum::<u64>()));
+
+        // but never less than a sample
+        remainder = 0.0;
+        for _ in 0..10 {
+            let interval = get_onset_interval(
+                EmissionMode::Synchronous,
+                0.5,
+                0.0,
+                &mut remainder,
+                &mut rng,
+            );
+            assert!(interval == 1 && remainder == 0.0);
+        }
+    }
+
+    #[test]
+    fn emit_quasi_synchronously() {
+        let (intervals, mean, deviation) = onset_intervals(EmissionMode::QuasiSynchronous, 0.5);
+
+        assert!(intervals
+            .iter()
+            .all(|interval| (240..=720).contains(interval)));
+        assert!((mean - 480.0).abs() < 5.0);
+
+        // uniformly distributed within 240 samples around the mean
+        let expected_deviation = 240.0 / 3_f64.sqrt();
+        assert!((deviation / expected_deviation - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn emit_asynchronously() {
+        let (intervals, mean, deviation) = onset_intervals(EmissionMode::Asynchronous, 0.5);
+
+        // exponentially distributed intervals deviate as much as their mean
+        assert!((mean - 480.0).abs() < 15.0);
+        assert!((deviation / mean - 1.0).abs() < 0.05);
+
+        // and 1 - 1/e of them are shorter than the mean
+        let shorter = intervals.iter().filter(|interval| **interval < 480).count();
+        let expected = 1.0 - (-1.0_f64).exp();
+        assert!((shorter as f64 / intervals.len() as f64 - expected).abs() < 0.02);
+
+        // which makes the amount of grains per 10 mean intervals poisson distributed, with a
+        // variance equal to its mean
+        let mut counts = [0_u32; 400];
+        let mut onset = 0;
+        for interval in intervals {
+            onset += interval;
+            if let Some(count) = counts.get_mut((onset / 4800) as usize) {
+                *count += 1;
+            }
+        }
+        let count_mean = counts.iter().sum::<u32>() as f64 / counts.len() as f64;
+        let count_variance = counts
+            .iter()
+            .map(|count| (*count as f64 - count_mean).powi(2))
+            .sum::<f64>()
+            / counts.len() as f64;
+        assert!((count_mean - 10.0).abs() < 0.5);
+        assert!((count_variance / count_mean - 1.0).abs() < 0.2);
+    }
 }
@@ -0,0 +1,189 @@
+use heapless::Vec;
+
+/// Maximum amount of steps of a pattern
+pub const MAX_STEPS: usize = 64;
+
+/// Step of a pattern, which starts a grain by chance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Step {
+    /// Chance of a grain starting on this step (between 0 and 1)
+    pub probability: f32,
+    /// Velocity of the grain relative to the `Velocity` parameter (between 0 and 1)
+    pub accent: f32,
+}
+
+impl Step {
+    /// Always starts a grain at full velocity
+    pub const HIT: Step = Step::new(1.0, 1.0);
+    /// Never starts a grain
+    pub const REST: Step = Step::new(0.0, 1.0);
+
+    pub const fn new(probability: f32, accent: f32) -> Self {
+        Step {
+            probability,
+            accent,
+        }
+    }
+}
+
+/// Rhythm grains are playing on the steps of the tempo clock. It starts over after its last
+/// step.
+#[derive(Debug, Clone)]
+pub struct StepPattern {
+    steps: Vec<Step, MAX_STEPS>,
+    position: usize, // step which is played next
+}
+
+impl StepPattern {
+    /// Creates a pattern of the given steps. Returns the amount of steps as error if there are
+    /// none or more than `MAX_STEPS`.
+    pub fn new(steps: &[Step]) -> Result<Self, usize> {
+        if steps.is_empty() {
+            return Err(0);
+        }
+
+        Ok(StepPattern {
+            steps: Vec::from_slice(steps).map_err(|_| steps.len())?,
+            position: 0,
+        })
+    }
+
+    /// Creates a Euclidean rhythm, which spreads `hits` as evenly as possible across `steps`
+    /// steps and starts at the step `rotation` of it. Returns the amount of steps as error if
+    /// there are none or more than `MAX_STEPS`.
+    pub fn euclidean(hits: usize, steps: usize, rotation: usize) -> Result<Self, usize> {
+        if steps == 0 || steps > MAX_STEPS {
+            return Err(steps);
+        }
+
+        let hits = bjorklund(hits.min(steps), steps);
+        Ok(StepPattern {
+            steps: (0..steps)
+                .map(|step| match hits[(step + rotation) % steps] {
+                    true => Step::HIT,
+                    false => Step::REST,
+                })
+                .collect(),
+            position: 0,
+        })
+    }
+
+    #[cfg(test)]
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Starts over at the first step.
+    pub fn restart(&mut self) {
+        self.position = 0;
+    }
+
+    /// Returns the next step and moves on to the one after it.
+    pub fn next_step(&mut self) -> Step {
+        let step = self.steps[self.position];
+        self.position = (self.position + 1) % self.steps.len();
+        step
+    }
+}
+
+/// Spreads `hits` as evenly as possible across `steps` steps with Bjorklund's algorithm. It
+/// starts with a group for every hit and every rest, and keeps appending the remaining groups to
+/// the leading ones until at most one remains. All leading groups are the same, just like all
+/// remaining ones, so only one of each has to be kept.
+fn bjorklund(hits: usize, steps: usize) -> Vec<bool, MAX_STEPS> {
+    if hits == 0 {
+        return (0..steps).map(|_| false).collect();
+    }
+
+    let (mut front, mut front_count): (Vec<bool, MAX_STEPS>, usize) = (Vec::new(), hits);
+    let (mut back, mut back_count): (Vec<bool, MAX_STEPS>, usize) = (Vec::new(), steps - hits);
+    front.push(true).unwrap();
+    back.push(false).unwrap();
+
+    while back_count > 1 {
+        let mut joined = front.clone();
+        joined.extend_from_slice(&back).unwrap();
+
+        if front_count <= back_count {
+            back_count -= front_count;
+        } else {
+            // the leading groups which are left over remain
+            let left_over = front_count - back_count;
+            (front_count, back_count) = (back_count, left_over);
+            back = front;
+        }
+        front = joined;
+    }
+
+    let mut pattern = Vec::new();
+    for _ in 0..front_count {
+        pattern.extend_from_slice(&front).unwrap();
+    }
+    for _ in 0..back_count {
+        pattern.extend_from_slice(&back).unwrap();
+    }
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hits(pattern: &StepPattern) -> Vec<bool, MAX_STEPS> {
+        pattern
+            .steps()
+            .iter()
+            .map(|step| *step == Step::HIT)
+            .collect()
+    }
+
+    #[test]
+    fn spread_hits_evenly() {
+        // the tresillo
+        let pattern = StepPattern::euclidean(3, 8, 0).unwrap();
+        assert!(hits(&pattern) == [true, false, false, true, false, false, true, false]);
+
+        let pattern = StepPattern::euclidean(5, 8, 0).unwrap();
+        // the cinquillo
+        assert!(hits(&pattern) == [true, false, true, true, false, true, true, false]);
+
+        let pattern = StepPattern::euclidean(4, 12, 0).unwrap();
+        assert!(hits(&pattern)
+            .chunks(3)
+            .all(|group| group == [true, false, false]));
+
+        assert!(hits(&StepPattern::euclidean(0, 4, 0).unwrap()) == [false; 4]);
+        assert!(hits(&StepPattern::euclidean(9, 4, 0).unwrap()) == [true; 4]);
+    }
+
+    #[test]
+    fn rotate_a_euclidean_rhythm() {
+        let pattern = StepPattern::euclidean(3, 8, 3).unwrap();
+        assert!(hits(&pattern) == [true, false, false, true, false, true, false, false]);
+
+        // rotating by a whole cycle changes nothing
+        let pattern = StepPattern::euclidean(3, 8, 8).unwrap();
+        assert!(hits(&pattern) == hits(&StepPattern::euclidean(3, 8, 0).unwrap()));
+    }
+
+    #[test]
+    fn reject_invalid_patterns() {
+        assert!(StepPattern::euclidean(1, 0, 0).unwrap_err() == 0);
+        assert!(StepPattern::euclidean(1, MAX_STEPS + 1, 0).unwrap_err() == MAX_STEPS + 1);
+        assert!(StepPattern::new(&[]).unwrap_err() == 0);
+        assert!(StepPattern::new(&[Step::HIT; MAX_STEPS + 1]).unwrap_err() == MAX_STEPS + 1);
+    }
+
+    #[test]
+    fn repeat_the_steps() {
+        let accented = Step::new(0.5, 0.25);
+        let mut pattern = StepPattern::new(&[Step::HIT, accented, Step::REST]).unwrap();
+
+        let steps = [0; 7].map(|_| pattern.next_step());
+        assert!(steps[..3] == steps[3..6]);
+        assert!(steps[1] == accented && steps[6] == Step::HIT);
+
+        pattern.restart();
+        assert!(pattern.next_step() == Step::HIT);
+    }
+}
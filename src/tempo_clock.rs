@@ -0,0 +1,261 @@
+use crate::manager::MAX_GRAINS;
+
+use heapless::Vec;
+
+/// Length of the steps of the tempo clock as a musical note value. The number is the
+/// denominator of the note, i.e. `Straight(4)` is a quarter note and `Triplet(8)` an eighth
+/// note triplet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteDivision {
+    Straight(u8),
+    /// Three notes in the time of two
+    Triplet(u8),
+    /// One and a half times as long
+    Dotted(u8),
+}
+
+impl NoteDivision {
+    /// Returns the length of the note in quarter notes (beats).
+    pub fn beats(self) -> f32 {
+        match self {
+            NoteDivision::Straight(note) => 4.0 / note.max(1) as f32,
+            NoteDivision::Triplet(note) => 4.0 / note.max(1) as f32 * 2.0 / 3.0,
+            NoteDivision::Dotted(note) => 4.0 / note.max(1) as f32 * 1.5,
+        }
+    }
+}
+
+/// Divides a tempo into steps of a note division, on which grains are being started. The steps
+/// are either counted in samples from the tempo in BPM or in ticks of an external clock with a
+/// fixed resolution in pulses per quarter note (PPQN), i.e. MIDI clock.
+#[derive(Debug)]
+pub struct TempoClock {
+    bpm: f32,
+    division: NoteDivision,
+    swing: f32, // delays every second step by up to half a step
+
+    ppqn: Option<u32>,     // counts the steps in external ticks if set
+    step: u64,             // next step since the clock was restarted
+    ticks_until_step: f32, // external ticks from the current one until the next step
+    remainder: f32,        // fraction of a sample the previous intervals have been cut short by
+}
+
+impl TempoClock {
+    pub fn new() -> Self {
+        TempoClock {
+            bpm: 120.0,
+            division: NoteDivision::Straight(16),
+            swing: 0.0,
+
+            ppqn: None,
+            step: 0,
+            ticks_until_step: 0.0,
+            remainder: 0.0,
+        }
+    }
+
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.bpm = bpm.clamp(1.0, 999.0);
+    }
+
+    pub fn set_division(&mut self, division: NoteDivision) {
+        self.division = division;
+    }
+
+    pub fn set_swing(&mut self, swing: f32) {
+        self.swing = swing.clamp(0.0, 1.0);
+    }
+
+    /// Counts the steps in ticks of an external clock with the given resolution, or in samples
+    /// from the tempo if `None`. Returns the resolution as error if it is 0.
+    pub fn set_external_clock(&mut self, ppqn: Option<u32>) -> Result<(), u32> {
+        if ppqn == Some(0) {
+            return Err(0);
+        }
+
+        self.ppqn = ppqn;
+        self.restart();
+        Ok(())
+    }
+
+    pub fn is_external(&self) -> bool {
+        self.ppqn.is_some()
+    }
+
+    /// Starts over at the first step, which is due immediately.
+    pub fn restart(&mut self) {
+        self.step = 0;
+        self.ticks_until_step = 0.0;
+        self.remainder = 0.0;
+    }
+
+    /// Returns the amount of samples from the previous step until the next one and moves on to
+    /// it. The first step after a restart is due immediately.
+    pub fn next_interval(&mut self, fs: usize) -> u64 {
+        let steps = match self.step {
+            0 => 0.0,
+            step => self.step_length(step - 1),
+        };
+        self.step += 1;
+
+        let samples_per_step = self.division.beats() * 60.0 / self.bpm * fs as f32;
+        let interval = steps * samples_per_step + self.remainder;
+
+        // carry the fraction over, so the clock doesn't drift
+        let samples = interval as u64;
+        self.remainder = interval - samples as f32;
+        samples
+    }
+
+    /// Advances the external clock by a single tick and returns the steps which are due until
+    /// the next one, as their offsets from this tick in fractions of a tick. The first tick after
+    /// a restart is the first step.
+    ///
+    /// Each step is counted from the previous one, so changing the division only affects the
+    /// steps after the next one.
+    pub fn tick(&mut self) -> Vec<f32, MAX_GRAINS> {
+        let mut due = Vec::new();
+        let Some(ppqn) = self.ppqn else {
+            return due;
+        };
+
+        // a step right before the next tick is due on it, rounding errors aside
+        let ticks_per_step = ppqn as f32 * self.division.beats();
+        while self.ticks_until_step < 0.999 {
+            due.push(self.ticks_until_step.max(0.0)).ok();
+            self.ticks_until_step += self.step_length(self.step) * ticks_per_step;
+            self.step += 1;
+        }
+
+        self.ticks_until_step -= 1.0;
+        due
+    }
+
+    /// Returns the length of the given step in steps, which is stretched or shortened by the
+    /// swing.
+    fn step_length(&self, step: u64) -> f32 {
+        self.swung_position(step + 1) - self.swung_position(step)
+    }
+
+    /// Returns the position of the given step in steps, with every second one delayed by the
+    /// swing.
+    fn swung_position(&self, step: u64) -> f32 {
+        match step % 2 {
+            0 => step as f32,
+            _ => step as f32 + 0.5 * self.swing,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FS: usize = 48_000;
+
+    #[test]
+    fn divide_a_quarter_note() {
+        assert!(NoteDivision::Straight(4).beats() == 1.0);
+        assert!(NoteDivision::Straight(16).beats() == 0.25);
+        assert!((NoteDivision::Triplet(8).beats() - 1.0 / 3.0).abs() < 0.0001);
+        assert!(NoteDivision::Dotted(8).beats() == 0.75);
+    }
+
+    #[test]
+    fn count_steps_in_samples() {
+        let mut clock = TempoClock::new();
+
+        // sixteenth notes at 120 BPM
+        assert!(clock.next_interval(FS) == 0);
+        assert!(clock.next_interval(FS) == 6000);
+
+        clock.set_division(NoteDivision::Triplet(8));
+        assert!(clock.next_interval(FS) == 8000);
+        clock.set_division(NoteDivision::Dotted(8));
+        assert!(clock.next_interval(FS) == 18000);
+
+        clock.set_tempo(60.0);
+        clock.set_division(NoteDivision::Straight(4));
+        assert!(clock.next_interval(FS) == 48000);
+    }
+
+    #[test]
+    fn swing_every_second_step() {
+        let mut clock = TempoClock::new();
+        clock.set_swing(0.5);
+
+        let intervals = [0; 5].map(|_| clock.next_interval(FS));
+        assert!(intervals == [0, 7500, 4500, 7500, 4500]);
+    }
+
+    #[test]
+    fn dont_drift() {
+        let mut clock = TempoClock::new();
+        clock.set_tempo(97.0);
+
+        // 1000 sixteenth notes are 250 beats long
+        let samples: u64 = (0..=1000).map(|_| clock.next_interval(FS)).sum();
+        let expected = 250.0 * 60.0 / 97.0 * FS as f32;
+        assert!((samples as f32 - expected).abs() <= 1.0);
+    }
+
+    /// Returns the ticks steps are due on.
+    fn due_ticks(clock: &mut TempoClock, ticks: usize) -> Vec<usize, 64> {
+        (0..ticks).filter(|_| !clock.tick().is_empty()).collect()
+    }
+
+    #[test]
+    fn count_steps_in_external_ticks() {
+        let mut clock = TempoClock::new();
+        assert!(clock.tick().is_empty());
+
+        // sixteenth notes are 6 ticks long at 24 PPQN
+        clock.set_external_clock(Some(24)).unwrap();
+        assert!(due_ticks(&mut clock, 24) == [0, 6, 12, 18]);
+
+        // swung by half, every second one is delayed by 1.5 ticks
+        clock.restart();
+        clock.set_swing(0.5);
+        let steps = [0; 24].map(|_| clock.tick());
+        let due: Vec<usize, 24> = (0..24).filter(|tick| !steps[*tick].is_empty()).collect();
+        assert!(due == [0, 7, 12, 19]);
+        assert!(steps[7] == [0.5] && steps[12] == [0.0]);
+
+        // dotted eighth notes at 48 PPQN
+        clock.set_swing(0.0);
+        clock.set_division(NoteDivision::Dotted(8));
+        clock.set_external_clock(Some(48)).unwrap();
+        assert!(due_ticks(&mut clock, 80) == [0, 36, 72]);
+
+        assert!(clock.set_external_clock(Some(0)) == Err(0));
+        assert!(clock.is_external());
+    }
+
+    #[test]
+    fn change_the_division_between_ticks() {
+        let mut clock = TempoClock::new();
+        clock.set_external_clock(Some(24)).unwrap();
+        assert!(due_ticks(&mut clock, 9) == [0, 6]);
+
+        // the step on tick 12 is kept, the ones after it are eighth notes
+        clock.set_division(NoteDivision::Straight(8));
+        assert!(due_ticks(&mut clock, 39) == [3, 15, 27]);
+    }
+
+    #[test]
+    fn count_several_steps_per_tick() {
+        let mut clock = TempoClock::new();
+        clock.set_external_clock(Some(1)).unwrap();
+        clock.set_division(NoteDivision::Straight(16));
+
+        // four sixteenth notes per quarter note tick
+        for _ in 0..10 {
+            assert!(clock.tick() == [0.0, 0.25, 0.5, 0.75]);
+        }
+
+        // swung sixteenths
+        clock.restart();
+        clock.set_swing(0.5);
+        assert!(clock.tick() == [0.0, 0.3125, 0.5, 0.8125]);
+    }
+}
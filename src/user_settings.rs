@@ -13,6 +13,15 @@ pub enum GranulatorParameter {
     VelocitySpread,
     DelaySpread,
     WindowParam,
+    Pan,
+    PanSpread,
+    ReverseProbability,
+    WindowSkew,
+    WindowSkewSpread,
+    WindowMorph,
+    Density,
+    DensitySpread,
+    Jitter,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -25,6 +34,7 @@ pub struct UserSettings {
     pub pitch: f32,
     pub delay: f32,
     pub velocity: f32,
+    pub pan: f32,
 
     // spread parameters
     pub sp_offset: f32,
@@ -32,14 +42,29 @@ pub struct UserSettings {
     pub sp_pitch: f32,
     pub sp_delay: f32,
     pub sp_velocity: f32,
+    pub sp_pan: f32,
+
+    // grain emission, 0 = refill, 1 = synchronous, 2 = quasi-synchronous, 3 = asynchronous,
+    // 4 = tempo
+    pub emission_mode: u8,
+    pub density: f32,
+    pub sp_density: f32,
+    pub jitter: f32,
 
     // window function parameters
     pub window_function: u8,
     pub window_param: f32,
+    pub window_skew: f32,
+    pub sp_window_skew: f32,
+    pub window_morph: f32,
 
     // musical pitch
     pub scale: u8,
     pub mode: u8,
+
+    // playback direction
+    pub reverse_probability: f32,
+    pub ping_pong: bool,
 }
 
 impl UserSettings {
@@ -52,18 +77,31 @@ impl UserSettings {
             pitch: 0.5,
             delay: 0.0,
             velocity: 1.0,
+            pan: 0.5,
 
             sp_offset: 0.0,
             sp_grain_size: 0.0,
             sp_pitch: 0.0,
             sp_delay: 0.0,
             sp_velocity: 0.0,
+            sp_pan: 0.0,
+
+            emission_mode: 0,
+            density: 1.0 / 3.0, // 10 grains per second
+            sp_density: 0.0,
+            jitter: 0.0,
 
             window_function: 0,
             window_param: 0.0,
+            window_skew: 0.5,
+            sp_window_skew: 0.0,
+            window_morph: 0.0,
 
             scale: 0,
             mode: 0,
+
+            reverse_probability: 0.0,
+            ping_pong: false,
         }
     }
 }
@@ -0,0 +1,539 @@
+use core::mem::size_of;
+
+#[cfg(feature = "std")]
+use std::{fs::File, io::Read, path::Path, vec::Vec};
+
+use crate::sample::{Sample, I24};
+
+const FORMAT_PCM: u16 = 0x0001;
+const FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// Everything that can go wrong while reading a WAV file
+#[derive(Debug)]
+pub enum WavError {
+    /// The file doesn't start with a RIFF/WAVE header
+    NotAWavFile,
+    /// A chunk header or the format chunk is incomplete or inconsistent
+    MalformedChunk,
+    /// The file contains no format chunk
+    MissingFormatChunk,
+    /// The file contains no data chunk
+    MissingDataChunk,
+    /// The format tag and bit depth as (format tag, bits per sample)
+    UnsupportedFormat(u16, u16),
+    /// The data chunk is shorter than declared or ends within a frame
+    TruncatedData,
+    /// The requested sample type doesn't match the format of the file
+    SampleTypeMismatch,
+    /// The data chunk doesn't start at an address aligned for the requested sample type
+    MisalignedData,
+    /// The file has more than `MAX_CHANNELS` channels
+    TooManyChannels(usize),
+    /// Reading the file failed
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for WavError {
+    fn from(error: std::io::Error) -> Self {
+        WavError::Io(error)
+    }
+}
+
+/// Encoding of the samples in the data chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WavSampleFormat {
+    /// Unsigned 8 bit or signed 16, 24 and 32 bit integers
+    Pcm,
+    /// 32 or 64 bit floating point values
+    Float,
+}
+
+/// Contents of the format chunk of a WAV file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavSpec {
+    pub format: WavSampleFormat,
+    pub channels: usize,
+    pub sample_rate: usize,
+    pub bits_per_sample: usize,
+}
+
+impl WavSpec {
+    /// Size of a single frame of interleaved samples in bytes.
+    pub fn frame_size(&self) -> usize {
+        self.channels * self.bits_per_sample / 8
+    }
+}
+
+fn read_u16(bytes: &[u8], position: usize) -> u16 {
+    u16::from_le_bytes([bytes[position], bytes[position + 1]])
+}
+
+fn read_u32(bytes: &[u8], position: usize) -> u32 {
+    let mut value = [0; 4];
+    value.copy_from_slice(&bytes[position..position + 4]);
+    u32::from_le_bytes(value)
+}
+
+fn parse_format_chunk(chunk: &[u8]) -> Result<WavSpec, WavError> {
+    if chunk.len() < 16 {
+        return Err(WavError::MalformedChunk);
+    }
+
+    let mut format_tag = read_u16(chunk, 0);
+    let channels = read_u16(chunk, 2) as usize;
+    let sample_rate = read_u32(chunk, 4) as usize;
+    let block_align = read_u16(chunk, 12) as usize;
+    let bits_per_sample = read_u16(chunk, 14);
+
+    // the actual format tag is stored in the first two bytes of the sub format
+    if format_tag == FORMAT_EXTENSIBLE {
+        if chunk.len() < 40 {
+            return Err(WavError::MalformedChunk);
+        }
+        format_tag = read_u16(chunk, 24);
+    }
+
+    let format = match (format_tag, bits_per_sample) {
+        (FORMAT_PCM, 8 | 16 | 24 | 32) => WavSampleFormat::Pcm,
+        (FORMAT_IEEE_FLOAT, 32 | 64) => WavSampleFormat::Float,
+        (format_tag, bits_per_sample) => {
+            return Err(WavError::UnsupportedFormat(format_tag, bits_per_sample))
+        }
+    };
+
+    let spec = WavSpec {
+        format,
+        channels,
+        sample_rate,
+        bits_per_sample: bits_per_sample as usize,
+    };
+
+    if channels == 0 || block_align != spec.frame_size() {
+        return Err(WavError::MalformedChunk);
+    }
+
+    Ok(spec)
+}
+
+/// Sample types that can be read straight from the data chunk of a WAV file.
+///
+/// # Safety
+/// Every little endian byte pattern of `size_of::<Self>()` bytes has to be a valid sample of the
+/// given format and bit depth, so the data chunk can be reinterpreted without copying it.
+pub unsafe trait WavSample: Sample {
+    const FORMAT: WavSampleFormat;
+    const BITS_PER_SAMPLE: usize;
+}
+
+unsafe impl WavSample for u8 {
+    const FORMAT: WavSampleFormat = WavSampleFormat::Pcm;
+    const BITS_PER_SAMPLE: usize = 8;
+}
+
+// bytes of packed samples are always being read in little endian order
+unsafe impl WavSample for I24 {
+    const FORMAT: WavSampleFormat = WavSampleFormat::Pcm;
+    const BITS_PER_SAMPLE: usize = 24;
+}
+
+#[cfg(target_endian = "little")]
+unsafe impl WavSample for i16 {
+    const FORMAT: WavSampleFormat = WavSampleFormat::Pcm;
+    const BITS_PER_SAMPLE: usize = 16;
+}
+
+#[cfg(target_endian = "little")]
+unsafe impl WavSample for i32 {
+    const FORMAT: WavSampleFormat = WavSampleFormat::Pcm;
+    const BITS_PER_SAMPLE: usize = 32;
+}
+
+#[cfg(target_endian = "little")]
+unsafe impl WavSample for f32 {
+    const FORMAT: WavSampleFormat = WavSampleFormat::Float;
+    const BITS_PER_SAMPLE: usize = 32;
+}
+
+#[cfg(target_endian = "little")]
+unsafe impl WavSample for f64 {
+    const FORMAT: WavSampleFormat = WavSampleFormat::Float;
+    const BITS_PER_SAMPLE: usize = 64;
+}
+
+/// WAV file which is parsed in place, e.g. from memory mapped flash. Its data chunk is never
+/// being copied.
+#[derive(Debug, Clone, Copy)]
+pub struct WavView<'a> {
+    pub spec: WavSpec,
+    pub data: &'a [u8], // raw bytes of the data chunk
+}
+
+impl<'a> WavView<'a> {
+    /// Parses the chunks of a WAV file and finds its `fmt ` and `data` chunks. All other chunks
+    /// are being skipped.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, WavError> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            return Err(WavError::NotAWavFile);
+        }
+
+        let mut spec = None;
+        let mut data = None;
+
+        let mut position = 12;
+        while position < bytes.len() {
+            if position + 8 > bytes.len() {
+                return Err(WavError::MalformedChunk);
+            }
+
+            let id = &bytes[position..position + 4];
+            let size = read_u32(bytes, position + 4) as usize;
+            let start = position + 8;
+            let end = start.saturating_add(size);
+
+            if end > bytes.len() {
+                return Err(match id {
+                    b"data" => WavError::TruncatedData,
+                    _ => WavError::MalformedChunk,
+                });
+            }
+
+            match id {
+                b"fmt " => spec = Some(parse_format_chunk(&bytes[start..end])?),
+                b"data" => data = Some(&bytes[start..end]),
+                _ => (),
+            }
+
+            // chunks are padded to an even size
+            position = end + (size & 1);
+        }
+
+        let spec = spec.ok_or(WavError::MissingFormatChunk)?;
+        let data = data.ok_or(WavError::MissingDataChunk)?;
+
+        if data.len() % spec.frame_size() != 0 {
+            return Err(WavError::TruncatedData);
+        }
+
+        Ok(WavView { spec, data })
+    }
+
+    /// Returns the samples of the data chunk as interleaved frames without copying them.
+    ///
+    /// The sample type has to match the format of the file and the data chunk has to be
+    /// aligned for it in memory.
+    pub fn samples<T: WavSample>(&self) -> Result<&'a [T], WavError> {
+        if T::FORMAT != self.spec.format || T::BITS_PER_SAMPLE != self.spec.bits_per_sample {
+            return Err(WavError::SampleTypeMismatch);
+        }
+
+        let ptr = self.data.as_ptr().cast::<T>();
+        if !ptr.is_aligned() {
+            return Err(WavError::MisalignedData);
+        }
+
+        // format, size and alignment have been checked, `WavSample` guarantees the rest
+        Ok(unsafe { core::slice::from_raw_parts(ptr, self.data.len() / size_of::<T>()) })
+    }
+}
+
+/// Parses the chunks of a WAV file and returns its format together with the raw bytes of its
+/// data chunk. Chunks other than `fmt ` and `data` are being skipped. Same as `WavView::parse`.
+pub fn parse_wav(bytes: &[u8]) -> Result<(WavSpec, &[u8]), WavError> {
+    WavView::parse(bytes).map(|view| (view.spec, view.data))
+}
+
+/// Audio buffer loaded from a WAV file. Multi-channel files stay interleaved.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct WavBuffer {
+    pub samples: Vec<f32>,
+    pub sample_rate: usize,
+    pub channels: usize,
+}
+
+#[cfg(feature = "std")]
+fn decode_samples<T: Sample, const N: usize>(
+    data: &[u8],
+    from_bytes: fn([u8; N]) -> T,
+) -> Vec<f32> {
+    data.chunks_exact(N)
+        .map(|bytes| {
+            let mut sample = [0; N];
+            sample.copy_from_slice(bytes);
+            from_bytes(sample).to_f32()
+        })
+        .collect()
+}
+
+/// Decodes a whole WAV file in memory into floating point samples.
+#[cfg(feature = "std")]
+pub fn decode_wav(bytes: &[u8]) -> Result<WavBuffer, WavError> {
+    let WavView { spec, data } = WavView::parse(bytes)?;
+
+    let samples = match (spec.format, spec.bits_per_sample) {
+        (WavSampleFormat::Pcm, 8) => decode_samples(data, u8::from_le_bytes),
+        (WavSampleFormat::Pcm, 16) => decode_samples(data, i16::from_le_bytes),
+        (WavSampleFormat::Pcm, 24) => decode_samples(data, I24),
+        (WavSampleFormat::Pcm, _) => decode_samples(data, i32::from_le_bytes),
+        (WavSampleFormat::Float, 32) => decode_samples(data, f32::from_le_bytes),
+        (WavSampleFormat::Float, _) => decode_samples(data, f64::from_le_bytes),
+    };
+
+    Ok(WavBuffer {
+        samples,
+        sample_rate: spec.sample_rate,
+        channels: spec.channels,
+    })
+}
+
+/// Reads and decodes a WAV file from the given reader.
+#[cfg(feature = "std")]
+pub fn read_wav<R: Read>(mut reader: R) -> Result<WavBuffer, WavError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    decode_wav(&bytes)
+}
+
+/**
+Loads a WAV file with PCM (8, 16, 24 or 32 bit) or IEEE float (32 or 64 bit) samples and any
+amount of channels.
+
+## Example
+
+```no_run
+let wav = granulator::load_wav("sample.wav").unwrap();
+
+let mut g = granulator::Granulator::new(wav.sample_rate);
+g.set_interleaved_audio_buffer(&wav.samples, wav.channels)
+    .unwrap();
+```
+*/
+#[cfg(feature = "std")]
+pub fn load_wav<P: AsRef<Path>>(path: P) -> Result<WavBuffer, WavError> {
+    read_wav(File::open(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate std;
+    use std::vec::Vec;
+
+    fn wav_file(format_tag: u16, channels: u16, bits: u16, data: &[u8]) -> Vec<u8> {
+        let block_align = channels * bits / 8;
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WAVE");
+
+        bytes.extend_from_slice(b"fmt ");
+        bytes.extend_from_slice(&16_u32.to_le_bytes());
+        bytes.extend_from_slice(&format_tag.to_le_bytes());
+        bytes.extend_from_slice(&channels.to_le_bytes());
+        bytes.extend_from_slice(&48_000_u32.to_le_bytes());
+        bytes.extend_from_slice(&(48_000 * block_align as u32).to_le_bytes());
+        bytes.extend_from_slice(&block_align.to_le_bytes());
+        bytes.extend_from_slice(&bits.to_le_bytes());
+
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    /// Bytes placed `offset` bytes behind an address aligned to 8 bytes, so the data chunk of a
+    /// file built by `wav_file` (at byte 44) is aligned for every sample type with an offset of 0.
+    struct Aligned {
+        words: Vec<u64>,
+        offset: usize,
+        length: usize,
+    }
+
+    impl Aligned {
+        fn new(bytes: &[u8], offset: usize) -> Self {
+            let mut words = std::vec![0_u64; (offset + bytes.len()).div_ceil(8)];
+
+            // fill the words byte by byte in memory order
+            let memory = unsafe {
+                core::slice::from_raw_parts_mut(words.as_mut_ptr().cast::<u8>(), words.len() * 8)
+            };
+            memory[offset..offset + bytes.len()].copy_from_slice(bytes);
+
+            Aligned {
+                words,
+                offset,
+                length: bytes.len(),
+            }
+        }
+
+        fn bytes(&self) -> &[u8] {
+            let memory = unsafe {
+                core::slice::from_raw_parts(self.words.as_ptr().cast::<u8>(), self.words.len() * 8)
+            };
+            &memory[self.offset..self.offset + self.length]
+        }
+    }
+
+    #[test]
+    fn parse_a_stereo_file() {
+        let file = wav_file(FORMAT_PCM, 2, 16, &[0; 8]);
+        let WavView { spec, data } = WavView::parse(&file).unwrap();
+        assert!(parse_wav(&file).unwrap() == (spec, data));
+
+        assert!(spec.format == WavSampleFormat::Pcm);
+        assert!(spec.channels == 2 && spec.sample_rate == 48_000);
+        assert!(spec.frame_size() == 4);
+        assert!(data.len() == 8);
+    }
+
+    #[test]
+    fn skip_unknown_chunks() {
+        let mut file = wav_file(FORMAT_IEEE_FLOAT, 1, 32, &[0; 4]);
+
+        // odd sized chunk with a padding byte in front of the format chunk
+        let list = [b"LIST".as_slice(), &3_u32.to_le_bytes(), &[1, 2, 3, 0]].concat();
+        file.splice(12..12, list);
+
+        let WavView { spec, data } = WavView::parse(&file).unwrap();
+        assert!(spec.format == WavSampleFormat::Float);
+        assert!(data.len() == 4);
+    }
+
+    #[test]
+    fn report_errors() {
+        assert!(matches!(
+            WavView::parse(b"RIFX"),
+            Err(WavError::NotAWavFile)
+        ));
+
+        let file = wav_file(0x0055, 1, 16, &[0; 4]);
+        assert!(matches!(
+            WavView::parse(&file),
+            Err(WavError::UnsupportedFormat(0x0055, 16))
+        ));
+
+        let file = wav_file(FORMAT_IEEE_FLOAT, 1, 16, &[0; 4]);
+        assert!(matches!(
+            WavView::parse(&file),
+            Err(WavError::UnsupportedFormat(FORMAT_IEEE_FLOAT, 16))
+        ));
+
+        // data chunk is shorter than declared
+        let file = wav_file(FORMAT_PCM, 1, 16, &[0; 8]);
+        assert!(matches!(
+            WavView::parse(&file[..file.len() - 2]),
+            Err(WavError::TruncatedData)
+        ));
+
+        // data chunk ends within a frame
+        let file = wav_file(FORMAT_PCM, 2, 16, &[0; 6]);
+        assert!(matches!(
+            WavView::parse(&file),
+            Err(WavError::TruncatedData)
+        ));
+
+        // incomplete chunk header
+        let file = wav_file(FORMAT_PCM, 1, 16, &[0; 2]);
+        let file = [file.as_slice(), b"LI"].concat();
+        assert!(matches!(
+            WavView::parse(&file),
+            Err(WavError::MalformedChunk)
+        ));
+
+        // header only
+        assert!(matches!(
+            WavView::parse(&file[..12]),
+            Err(WavError::MissingFormatChunk)
+        ));
+    }
+
+    #[test]
+    fn view_samples_in_place() {
+        let file = Aligned::new(&wav_file(FORMAT_PCM, 2, 16, &[0x00, 0x80, 0x00, 0x40]), 0);
+        let view = WavView::parse(file.bytes()).unwrap();
+
+        let samples = view.samples::<i16>().unwrap();
+        assert!(samples == [i16::MIN, 0x4000]);
+        assert!(core::ptr::eq(samples.as_ptr().cast(), view.data.as_ptr()));
+
+        assert!(matches!(
+            view.samples::<f32>(),
+            Err(WavError::SampleTypeMismatch)
+        ));
+        assert!(matches!(
+            view.samples::<I24>(),
+            Err(WavError::SampleTypeMismatch)
+        ));
+
+        // packed samples can be read from any address
+        let file = Aligned::new(&wav_file(FORMAT_PCM, 1, 24, &[0x00, 0x00, 0xc0]), 1);
+        let view = WavView::parse(file.bytes()).unwrap();
+        assert!(view.samples::<I24>().unwrap()[0].to_f32() == -0.5);
+    }
+
+    #[test]
+    fn reject_misaligned_data() {
+        // moves the data chunk to an odd address
+        let file = Aligned::new(&wav_file(FORMAT_PCM, 1, 16, &[0; 4]), 1);
+        let view = WavView::parse(file.bytes()).unwrap();
+
+        assert!(matches!(
+            view.samples::<i16>(),
+            Err(WavError::MisalignedData)
+        ));
+    }
+
+    #[test]
+    fn play_a_wav_in_place() {
+        let data: Vec<u8> = [0.5_f32; 4_000]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        let file = Aligned::new(&wav_file(FORMAT_IEEE_FLOAT, 1, 32, &data), 0);
+
+        let mut g = crate::Granulator::new(48_000);
+        let spec = g.set_wav_audio_buffer(file.bytes()).unwrap();
+        assert!(spec.channels == 1 && spec.sample_rate == 48_000);
+
+        g.set_active_grains(10);
+        let mut output = [0.0; 960];
+        g.process_block(&mut output);
+        assert!(output.iter().any(|sample| *sample > 0.0));
+
+        // the sample type of the granulator doesn't match
+        let mut g = crate::Granulator::<i16>::with_sample_type(48_000);
+        assert!(matches!(
+            g.set_wav_audio_buffer(file.bytes()),
+            Err(WavError::SampleTypeMismatch)
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn decode_all_formats() {
+        let file = wav_file(FORMAT_PCM, 1, 8, &[0, 128]);
+        assert!(decode_wav(&file).unwrap().samples == [-1.0, 0.0]);
+
+        let file = wav_file(FORMAT_PCM, 2, 16, &[0x00, 0x80, 0x00, 0x40]);
+        let wav = decode_wav(&file).unwrap();
+        assert!(wav.samples == [-1.0, 0.5] && wav.channels == 2);
+
+        let file = wav_file(FORMAT_PCM, 1, 24, &[0x00, 0x00, 0xc0]);
+        assert!(decode_wav(&file).unwrap().samples == [-0.5]);
+
+        let file = wav_file(FORMAT_PCM, 1, 32, &i32::MIN.to_le_bytes());
+        assert!(decode_wav(&file).unwrap().samples == [-1.0]);
+
+        let file = wav_file(FORMAT_IEEE_FLOAT, 1, 32, &0.25_f32.to_le_bytes());
+        assert!(decode_wav(&file).unwrap().samples == [0.25]);
+
+        let file = wav_file(FORMAT_IEEE_FLOAT, 1, 64, &0.25_f64.to_le_bytes());
+        let wav = decode_wav(&file).unwrap();
+        assert!(wav.samples == [0.25] && wav.sample_rate == 48_000);
+    }
+}
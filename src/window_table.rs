@@ -0,0 +1,279 @@
+use super::grain::WindowFunction;
+
+#[cfg(feature = "no_std")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+/// Amount of segments a window is being rendered into. The table holds one more value, so both
+/// edges of the window are exact.
+pub const WINDOW_TABLE_SIZE: usize = 1024;
+
+/// Envelope designed by the user, which grains with the `Custom` window function are playing.
+/// It is rendered into a window table right away, so it doesn't have to be kept around.
+#[derive(Debug, Clone, Copy)]
+pub enum CustomWindow<'e> {
+    /// Values between 0 and 1, stretched across the whole grain
+    Table(&'e [f32]),
+    /// Segments between breakpoints in ascending order of time. The envelope keeps the level of
+    /// the first breakpoint before it and the level of the last one after it.
+    Breakpoints(&'e [Breakpoint]),
+}
+
+/// Point of a custom envelope, which is reached from the previous one along the given curve
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Breakpoint {
+    /// Position within the grain (between 0 and 1)
+    pub time: f32,
+    /// Level of the envelope (between 0 and 1)
+    pub level: f32,
+    /// Shape of the segment towards this breakpoint. 0 is linear, positive values start slow and
+    /// end fast and negative values the other way around.
+    pub curve: f32,
+}
+
+impl Breakpoint {
+    pub const fn new(time: f32, level: f32, curve: f32) -> Self {
+        Breakpoint { time, level, curve }
+    }
+}
+
+impl CustomWindow<'_> {
+    /// Checks that tables hold at least two values and breakpoints are in ascending order of
+    /// time. Returns the length of a table or the index of a breakpoint that is out of place
+    /// otherwise.
+    pub fn validate(&self) -> Result<(), usize> {
+        match self {
+            CustomWindow::Table(values) if values.len() < 2 => Err(values.len()),
+            CustomWindow::Table(_) => Ok(()),
+            CustomWindow::Breakpoints([]) => Err(0),
+            CustomWindow::Breakpoints(breakpoints) => {
+                match breakpoints
+                    .windows(2)
+                    .position(|pair| pair[1].time < pair[0].time)
+                {
+                    Some(index) => Err(index + 1),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    /// Returns the value of the envelope at the given phase (between 0 and 1).
+    pub fn value_at(&self, phase: f32) -> f32 {
+        let value = match self {
+            CustomWindow::Table(values) => {
+                let position = phase.clamp(0.0, 1.0) * (values.len() - 1) as f32;
+                let index = (position as usize).min(values.len() - 2);
+                let fraction = position - index as f32;
+
+                values[index] + (values[index + 1] - values[index]) * fraction
+            }
+            CustomWindow::Breakpoints(breakpoints) => {
+                match breakpoints.iter().position(|point| point.time > phase) {
+                    Some(0) => breakpoints[0].level,
+                    Some(index) => {
+                        let (from, to) = (breakpoints[index - 1], breakpoints[index]);
+                        let t = (phase - from.time) / (to.time - from.time);
+
+                        from.level + (to.level - from.level) * bend(t, to.curve)
+                    }
+                    None => breakpoints[breakpoints.len() - 1].level,
+                }
+            }
+        };
+
+        value.clamp(0.0, 1.0)
+    }
+}
+
+/// Bends a linear segment (between 0 and 1) exponentially by the given curve.
+fn bend(t: f32, curve: f32) -> f32 {
+    if curve.abs() < 0.001 {
+        t
+    } else {
+        ((curve * t).exp() - 1.0) / (curve.exp() - 1.0)
+    }
+}
+
+/// A window function rendered once for a single window parameter, so grains read their envelope
+/// with linear interpolation instead of computing it for every sample.
+#[derive(Debug)]
+pub struct WindowTable {
+    window: WindowFunction,
+    param: f32,
+    values: [f32; WINDOW_TABLE_SIZE + 1],
+}
+
+impl WindowTable {
+    pub fn new(window: WindowFunction, param: f32) -> Self {
+        let mut table = WindowTable {
+            window,
+            param,
+            values: [0.0; WINDOW_TABLE_SIZE + 1],
+        };
+        table.render(window, param);
+        table
+    }
+
+    /// Renders a custom envelope into a table for the `Custom` window function.
+    pub fn from_custom(custom: &CustomWindow) -> Self {
+        let mut table = WindowTable {
+            window: WindowFunction::Custom,
+            param: 0.0,
+            values: [0.0; WINDOW_TABLE_SIZE + 1],
+        };
+        for (i, value) in table.values.iter_mut().enumerate() {
+            *value = custom.value_at(i as f32 / WINDOW_TABLE_SIZE as f32);
+        }
+        table
+    }
+
+    /// Renders the given window into the table, replacing the previous one.
+    pub fn render(&mut self, window: WindowFunction, param: f32) {
+        self.window = window;
+        self.param = param;
+        for (i, value) in self.values.iter_mut().enumerate() {
+            *value = window.value_at(i as f32 / WINDOW_TABLE_SIZE as f32, param);
+        }
+    }
+
+    /// Returns whether the table holds the given window with the given parameter.
+    pub fn matches(&self, window: WindowFunction, param: f32) -> bool {
+        self.window == window && self.param == param
+    }
+
+    /// Returns the value of the window at the given phase (between 0 and 1).
+    pub fn value_at(&self, phase: f32) -> f32 {
+        let position = phase.clamp(0.0, 1.0) * WINDOW_TABLE_SIZE as f32;
+        let index = (position as usize).min(WINDOW_TABLE_SIZE - 1);
+        let fraction = position - index as f32;
+
+        let (first, next) = (self.values[index], self.values[index + 1]);
+        first + (next - first) * fraction
+    }
+}
+
+/// All window tables the grains are reading their envelopes from
+#[derive(Debug, Default)]
+pub struct WindowTables {
+    pub selected: Option<WindowTable>, // the selected window, computed exactly if `None`
+    pub morph_target: Option<WindowTable>, // the window grains morph into, if any
+    pub custom: Option<WindowTable>,   // the custom envelope, if one has been set
+}
+
+impl WindowTables {
+    /// Returns the value of the given window at the given phase (between 0 and 1), read from a
+    /// table if possible. Custom windows fall back to a sine window until one has been set.
+    pub fn value_at(&self, window: WindowFunction, param: f32, phase: f32) -> f32 {
+        if let (WindowFunction::Custom, Some(custom)) = (window, &self.custom) {
+            return custom.value_at(phase);
+        }
+
+        [&self.selected, &self.morph_target]
+            .into_iter()
+            .flatten()
+            .find(|table| table.matches(window, param))
+            .map_or_else(
+                || window.value_at(phase, param),
+                |table| table.value_at(phase),
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follow_the_window_closely() {
+        for window in [
+            WindowFunction::Sine,
+            WindowFunction::Hann,
+            WindowFunction::Gaussian,
+            WindowFunction::Blackman,
+            WindowFunction::Kaiser,
+        ] {
+            let table = WindowTable::new(window, 0.5);
+
+            for i in 0..=3000 {
+                let phase = i as f32 / 3000.0;
+                let error = (table.value_at(phase) - window.value_at(phase, 0.5)).abs();
+                assert!(error < 0.0001, "{window:?} at {phase}");
+            }
+        }
+    }
+
+    #[test]
+    fn hit_both_edges() {
+        let table = WindowTable::new(WindowFunction::Hamming, 0.0);
+
+        assert!((table.value_at(0.0) - 0.08).abs() < 0.000_001);
+        assert!((table.value_at(1.0) - 0.08).abs() < 0.000_001);
+        assert!(table.value_at(1.5) == table.value_at(1.0));
+    }
+
+    #[test]
+    fn match_window_and_parameter() {
+        let mut table = WindowTable::new(WindowFunction::Tukey, 0.5);
+        assert!(table.matches(WindowFunction::Tukey, 0.5));
+        assert!(!table.matches(WindowFunction::Tukey, 0.25));
+        assert!(!table.matches(WindowFunction::Hann, 0.5));
+
+        table.render(WindowFunction::Hann, 0.0);
+        assert!(table.matches(WindowFunction::Hann, 0.0));
+        assert!((table.value_at(0.5) - 1.0).abs() < 0.000_001);
+    }
+
+    #[test]
+    fn stretch_a_custom_table() {
+        let custom = CustomWindow::Table(&[0.0, 1.0, 0.5]);
+
+        assert!(custom.value_at(0.0) == 0.0);
+        assert!(custom.value_at(0.25) == 0.5);
+        assert!(custom.value_at(0.5) == 1.0);
+        assert!(custom.value_at(1.0) == 0.5);
+    }
+
+    #[test]
+    fn follow_breakpoints() {
+        let breakpoints = [
+            Breakpoint::new(0.2, 0.0, 0.0),
+            Breakpoint::new(0.4, 1.0, 0.0),
+            Breakpoint::new(0.8, 0.5, 0.0),
+            Breakpoint::new(1.0, 0.0, 4.0),
+        ];
+        let custom = CustomWindow::Breakpoints(&breakpoints);
+
+        // holds the first level before the first breakpoint
+        assert!(custom.value_at(0.1) == 0.0);
+        assert!((custom.value_at(0.3) - 0.5).abs() < 0.0001);
+        assert!((custom.value_at(0.6) - 0.75).abs() < 0.0001);
+
+        // the last segment starts slow
+        assert!(custom.value_at(0.9) > 0.25);
+        assert!(custom.value_at(1.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn reject_invalid_custom_windows() {
+        assert!(CustomWindow::Table(&[1.0]).validate() == Err(1));
+        assert!(CustomWindow::Breakpoints(&[]).validate() == Err(0));
+
+        let unordered = [
+            Breakpoint::new(0.0, 0.0, 0.0),
+            Breakpoint::new(0.5, 1.0, 0.0),
+            Breakpoint::new(0.4, 0.0, 0.0),
+        ];
+        assert!(CustomWindow::Breakpoints(&unordered).validate() == Err(2));
+    }
+
+    #[test]
+    fn play_custom_windows_from_their_table() {
+        let mut tables = WindowTables::default();
+        assert!(tables.value_at(WindowFunction::Custom, 0.0, 0.5) == 1.0);
+
+        tables.custom = Some(WindowTable::from_custom(&CustomWindow::Table(&[0.5, 0.5])));
+        assert!(tables.value_at(WindowFunction::Custom, 0.0, 0.25) == 0.5);
+        assert!(tables.value_at(WindowFunction::Sine, 0.0, 0.5) == 1.0);
+    }
+}
@@ -1,20 +1,18 @@
-use granulator::{Granulator, MAX_GRAINS};
+use granulator::{StaticGranulator, MAX_GRAINS};
 use std::sync::{Arc, Mutex};
 use std::thread::{sleep, Builder};
 use std::time::Duration;
 
 const BUFFER_LENGTH: usize = 64;
 
+static MOCK_AUDIO_BUFFER: [f32; 10_000] = [1.0; 10_000];
+
 #[test]
 #[ignore]
 fn multi_threading() {
     // setup the variables
     let fs = 48000.0;
-    let mut granulator = Granulator::new(fs as usize);
-    let mut mock_audio_buffer = vec![];
-    for _ in 0..10_000 {
-        mock_audio_buffer.push(1.0);
-    }
+    let mut granulator = StaticGranulator::new(fs as usize);
 
     let audio_callback_interval = ((1_000_000.0 * BUFFER_LENGTH as f32) / fs) as u64; //ns
     let schedule_timer_interval = 20; //ms
@@ -27,7 +25,7 @@ fn multi_threading() {
     let schedule_timer_duration = Duration::from_millis(schedule_timer_interval);
 
     // setup granulator
-    granulator.set_audio_buffer(mock_audio_buffer.as_slice());
+    granulator.set_audio_buffer(&MOCK_AUDIO_BUFFER);
     granulator.set_active_grains(MAX_GRAINS);
     granulator.set_grain_size(10.0);
     granulator.set_master_volume(1.0);